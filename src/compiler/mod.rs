@@ -0,0 +1,333 @@
+//! Compiling parsed expressions into chunks the VM
+//! can run
+
+use crate::parser::ast::{Expr, UnaryOp, BinaryOp, LogicalOp};
+use crate::parser::tokens::Span;
+use crate::vm::VMError;
+use crate::vm::chunk::{Chunk, OpCode};
+use crate::vm::value::Value;
+
+/// Compiles an expression into a chunk that leaves
+/// the expression's value on the stack
+#[derive(Debug)]
+pub struct Compiler {
+    chunk: Chunk,
+    
+    /// Whether literals reuse an equal constant
+    /// already in the pool, trading a little compile
+    /// time for a smaller pool
+    pub dedup_constants: bool,
+    
+    /// The line and column of the last node that has
+    /// a span, recorded for the bytes emitted after it
+    position: (u32, u32)
+}
+
+impl Compiler {
+    /// Create a compiler with an empty chunk
+    pub fn new() -> Self {
+        Compiler {
+            chunk: Chunk::new(),
+            dedup_constants: false,
+            position: (1, 1)
+        }
+    }
+    
+    /// Compile `expr` into a chunk ending in
+    /// `OP_RETURN`
+    pub fn compile(mut self, expr: &Expr) -> Result<Chunk, VMError> {
+        self.expression(expr)?;
+        self.emit(OpCode::Return);
+        Ok(self.chunk)
+    }
+    
+    fn expression(&mut self, expr: &Expr) -> Result<(), VMError> {
+        match expr {
+            Expr::Literal(Value::Int(i)) if (-128..=127).contains(i) => {
+                self.emit(OpCode::SmallInt);
+                self.emit_byte(*i as i8 as u8);
+            },
+            Expr::Literal(value) => {
+                self.emit(OpCode::Constant);
+                self.emit_constant(value.clone())?;
+            },
+            Expr::Variable(name) => {
+                self.set_position(*name);
+                self.emit(OpCode::GetGlobal);
+                self.emit_constant(Value::from(name.as_slice()))?;
+            },
+            Expr::Unary { op, operand } => {
+                self.expression(operand)?;
+                match op {
+                    UnaryOp::Negate => self.emit(OpCode::Negate),
+                    UnaryOp::Plus => {},
+                    UnaryOp::BitNot => self.emit(OpCode::BitNot)
+                }
+            },
+            Expr::Binary { left, op: op @ (BinaryOp::Add | BinaryOp::Subtract), right }
+                if **right == Expr::Literal(Value::Int(1)) => {
+                self.expression(left)?;
+                self.emit(if *op == BinaryOp::Add { OpCode::Inc } else { OpCode::Dec });
+            },
+            Expr::Binary { left, op, right } => {
+                self.expression(left)?;
+                self.expression(right)?;
+                self.emit(match op {
+                    BinaryOp::Add => OpCode::Add,
+                    BinaryOp::Subtract => OpCode::Subtract,
+                    BinaryOp::Multiply => OpCode::Multiply,
+                    BinaryOp::Divide => OpCode::Divide,
+                    BinaryOp::Concat => OpCode::Concat,
+                    BinaryOp::BitAnd => OpCode::BitAnd,
+                    BinaryOp::BitOr => OpCode::BitOr,
+                    BinaryOp::BitXor => OpCode::BitXor,
+                    BinaryOp::ShiftLeft => OpCode::ShiftLeft,
+                    BinaryOp::ShiftRight => OpCode::ShiftRight,
+                    BinaryOp::Is => OpCode::Is
+                });
+            },
+            Expr::Logical { left, op: LogicalOp::And, right } => {
+                self.expression(left)?;
+                let end = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+                self.expression(right)?;
+                self.chunk.patch_jump(end)?;
+            },
+            Expr::Logical { left, op: LogicalOp::Or, right } => {
+                self.expression(left)?;
+                let right_side = self.emit_jump(OpCode::JumpIfFalse);
+                let end = self.emit_jump(OpCode::Jump);
+                self.chunk.patch_jump(right_side)?;
+                self.emit(OpCode::Pop);
+                self.expression(right)?;
+                self.chunk.patch_jump(end)?;
+            },
+            Expr::IfElse { condition, then_expr, else_expr } => {
+                self.expression(condition)?;
+                let else_branch = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+                self.expression(then_expr)?;
+                let end = self.emit_jump(OpCode::Jump);
+                self.chunk.patch_jump(else_branch)?;
+                self.emit(OpCode::Pop);
+                self.expression(else_expr)?;
+                self.chunk.patch_jump(end)?;
+            },
+            Expr::Assign { target, value } => {
+                self.expression(value)?;
+                self.set_position(*target);
+                self.emit(OpCode::SetGlobal);
+                self.emit_constant(Value::from(target.as_slice()))?;
+            },
+            Expr::Call { callee, args, span } => {
+                if args.len() > u8::MAX as usize {
+                    return Err(VMError::CompileError(format!(
+                        "too many arguments (the limit is {})",
+                        u8::MAX
+                    )));
+                }
+                self.expression(callee)?;
+                for arg in args {
+                    self.expression(arg)?;
+                }
+                self.set_position(*span);
+                self.emit(OpCode::Call);
+                self.emit_byte(args.len() as u8);
+            }
+        }
+        Ok(())
+    }
+    
+    /// Record the position of `span` for the bytes
+    /// emitted after it
+    fn set_position(&mut self, span: Span) {
+        self.position = (span.line, span.column);
+    }
+    
+    fn emit(&mut self, opcode: OpCode) {
+        self.emit_byte(opcode.to_byte());
+    }
+    
+    fn emit_byte(&mut self, byte: u8) {
+        let (line, column) = self.position;
+        self.chunk.push_byte_at(byte, line, column);
+    }
+    
+    /// Add a constant to the pool, reusing an equal
+    /// one if `dedup_constants` is set, and emit its
+    /// index as an operand
+    fn emit_constant(&mut self, value: Value) -> Result<(), VMError> {
+        let index = if self.dedup_constants {
+            self.chunk.push_constant_unique(value)
+        } else {
+            self.chunk.push_constant(value)
+        };
+        if index > u8::MAX as usize {
+            return Err(VMError::CompileError(format!(
+                "too many constants in one chunk (the limit is {})",
+                u8::MAX as usize + 1
+            )));
+        }
+        self.emit_byte(index as u8);
+        Ok(())
+    }
+    
+    /// Emit a forward jump with a placeholder operand,
+    /// returning its offset for `Chunk::patch_jump`
+    fn emit_jump(&mut self, opcode: OpCode) -> usize {
+        let offset = self.chunk.size();
+        self.emit(opcode);
+        self.emit_byte(0xff);
+        self.emit_byte(0xff);
+        offset
+    }
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_expr;
+    use crate::vm::VM;
+    
+    /// Compile and run `source`, returning the value
+    /// it leaves on the stack
+    fn run(source: &str) -> Result<Value, VMError> {
+        let chunk = Compiler::new().compile(&parse_expr(source).unwrap())?;
+        VM::new(chunk).interpret_verified()
+    }
+    
+    /// Compile `source`, returning the bytes of the
+    /// chunk's code
+    fn code(source: &str) -> Vec<u8> {
+        let chunk = Compiler::new().compile(&parse_expr(source).unwrap()).unwrap();
+        (0..chunk.size()).map(|offset| chunk.byte_at(offset)).collect()
+    }
+    
+    #[test]
+    fn dedup_constants() {
+        let expr = parse_expr("1000 + 1000 + 1000").unwrap();
+        let mut compiler = Compiler::new();
+        compiler.dedup_constants = true;
+        let deduped = compiler.compile(&expr).unwrap();
+        let plain = Compiler::new().compile(&expr).unwrap();
+        
+        assert_eq!(1, deduped.constant_count());
+        assert_eq!(3, plain.constant_count());
+        assert_eq!(Value::Int(1000), plain.read_const(1));
+        assert_eq!(Ok(Value::Int(3000)), VM::new(deduped).interpret_verified());
+        assert_eq!(Ok(Value::Int(3000)), VM::new(plain).interpret_verified());
+    }
+    
+    #[test]
+    fn small_int_literals() {
+        let chunk = Compiler::new().compile(&parse_expr("42").unwrap()).unwrap();
+        
+        assert_eq!(0, chunk.constant_count());
+        assert_eq!(vec![OpCode::SmallInt.to_byte(), 42, OpCode::Return.to_byte()], code("42"));
+        assert_eq!(Ok(Value::Int(-5)), run("-5"));
+        assert_eq!(Ok(Value::Int(128)), run("128"));
+    }
+    
+    #[test]
+    fn arithmetic() {
+        assert_eq!(Ok(Value::Int(7)), run("1 + 2 * 3"));
+        assert_eq!(Ok(Value::Int(-3)), run("-(1 + 2)"));
+        assert_eq!(Ok(Value::Int(6)), run("~1 & 6 | 2 << 1"));
+        assert_eq!(Ok(Value::from("ab")), run("\"a\" ++ \"b\""));
+        assert_eq!(Ok(Value::Bool(true)), run("1 is \"int\""));
+    }
+    
+    #[test]
+    fn negate() {
+        let chunk = Compiler::new().compile(&parse_expr("-1.5").unwrap()).unwrap();
+        let mut vm = VM::new(chunk);
+        vm.set_strict(true);
+        
+        assert_eq!(Ok(Value::Float(-1.5)), vm.interpret_verified());
+        match run("-0.0") {
+            Ok(Value::Float(x)) => assert!(x == 0.0 && x.is_sign_negative()),
+            other => panic!("expected a float, got {:?}", other)
+        }
+    }
+    
+    #[test]
+    fn inc_and_dec() {
+        let [get, inc, dec, ret] = [OpCode::GetGlobal, OpCode::Inc, OpCode::Dec, OpCode::Return]
+            .map(|opcode| opcode.to_byte());
+        
+        assert_eq!(vec![get, 0, inc, ret], code("x + 1"));
+        assert_eq!(vec![get, 0, dec, ret], code("x - 1"));
+        assert_eq!(vec![get, 0, dec, inc, ret], code("x - 1 + 1"));
+        assert_eq!(Ok(Value::Float(2.5)), run("1.5 + 1"));
+        assert_eq!(Ok(Value::Int(0)), run("1 - 1"));
+    }
+    
+    #[test]
+    fn control_flow() {
+        assert_eq!(Ok(Value::Int(1)), run("if true { 1 } else { 2 }"));
+        assert_eq!(Ok(Value::Int(2)), run("if false { 1 } else { 2 }"));
+        assert_eq!(Ok(Value::Bool(false)), run("false and missing"));
+        assert_eq!(Ok(Value::Bool(true)), run("true or missing"));
+        assert_eq!(Ok(Value::Bool(true)), run("false or true"));
+        assert_eq!(
+            Err(VMError::RuntimeError("undefined global 'missing'".to_string())),
+            run("true and missing")
+        );
+    }
+    
+    #[test]
+    fn globals_and_calls() {
+        assert_eq!(Ok(Value::from("0xff")), run("hex(255)"));
+        assert_eq!(
+            Err(VMError::RuntimeError("undefined global 'x'".to_string())),
+            run("x = 1")
+        );
+        
+        let chunk = Compiler::new().compile(&parse_expr("x = x + 1").unwrap()).unwrap();
+        let mut vm = VM::new(chunk);
+        vm.define_global("x", Value::Int(41));
+        
+        assert_eq!(Ok(Value::Int(42)), vm.interpret_verified());
+    }
+    
+    #[test]
+    fn call_arity_operand() {
+        let [get, small_int, call, ret] =
+            [OpCode::GetGlobal, OpCode::SmallInt, OpCode::Call, OpCode::Return]
+                .map(|opcode| opcode.to_byte());
+        
+        assert_eq!(vec![get, 0, small_int, 1, small_int, 2, call, 2, ret], code("f(1, 2)"));
+        let args = vec!["1"; 256].join(", ");
+        assert_eq!(
+            Err(VMError::CompileError("too many arguments (the limit is 255)".to_string())),
+            Compiler::new().compile(&parse_expr(&format!("f({})", args)).unwrap())
+        );
+    }
+    
+    #[test]
+    fn records_positions() {
+        let chunk = Compiler::new().compile(&parse_expr("1 +\n  f(2)").unwrap()).unwrap();
+        let call = chunk.instructions()
+            .find(|&(_, opcode)| opcode == OpCode::Call)
+            .map(|(offset, _)| offset)
+            .unwrap();
+        
+        assert_eq!((2, 3), (chunk.get_line(call), chunk.get_column(call)));
+    }
+    
+    #[test]
+    fn source_map() {
+        let chunk = Compiler::new().compile(&parse_expr("x +\n  f(y)").unwrap()).unwrap();
+        
+        assert_eq!(
+            vec![(0, 1, 1), (2, 2, 3), (4, 2, 5), (6, 2, 3), (8, 2, 3), (9, 2, 3)],
+            chunk.source_map()
+        );
+    }
+}
\ No newline at end of file
@@ -4,5 +4,7 @@ extern crate memchr;
 
 pub mod vm;
 pub mod parser;
+pub mod compiler;
 pub mod tools;
+pub mod prelude;
 mod internals;
\ No newline at end of file
@@ -1,26 +1,148 @@
 //! The structs and enums needed to build the
 //! abstract syntax tree for the program
 
+use crate::vm::value::Value;
+use crate::parser::tokens::Span;
+
 /// An enum representing the declarations that
 /// can be made at a global level
 #[derive(Debug, PartialEq)]
-enum Decl {}
+pub enum Decl<'a> {
+    /// A function declaration, e.g.
+    /// `fn add(a, b) { a + b }`
+    /// 
+    /// `doc` is the text of any `///` comment lines
+    /// before the declaration, and `body` is `None`
+    /// for an empty body.
+    Function {
+        doc: Option<String>,
+        name: Span<'a>,
+        params: Vec<Span<'a>>,
+        body: Option<Expr<'a>>
+    }
+}
 
 /// An enum representing the possible statements
+// Not parsed yet
+#[allow(dead_code)]
 #[derive(Debug, PartialEq)]
 enum Stmt {}
 
 /// An enum representing the possible expressions
 #[derive(Debug, PartialEq)]
-enum Expr {}
+pub enum Expr<'a> {
+    /// A literal value, e.g. `1` or `2.5`
+    Literal(Value),
+    
+    /// A reference to a variable by name, e.g. `x`,
+    /// along with where the name is in the source
+    Variable(Span<'a>),
+    
+    /// An operation on a single operand, e.g. `-a`
+    /// or `+a`
+    Unary {
+        op: UnaryOp,
+        operand: Box<Expr<'a>>
+    },
+    
+    /// An operation on two operands, e.g. `a + b`
+    Binary {
+        left: Box<Expr<'a>>,
+        op: BinaryOp,
+        right: Box<Expr<'a>>
+    },
+    
+    /// A short-circuiting logical operation, e.g.
+    /// `a and b`, which only evaluates `b` if it's
+    /// needed
+    Logical {
+        left: Box<Expr<'a>>,
+        op: LogicalOp,
+        right: Box<Expr<'a>>
+    },
+    
+    /// A conditional that produces a value, e.g.
+    /// `if a { b } else { c }`
+    /// 
+    /// Unlike an `if` statement, the `else` branch
+    /// is required.
+    IfElse {
+        condition: Box<Expr<'a>>,
+        then_expr: Box<Expr<'a>>,
+        else_expr: Box<Expr<'a>>
+    },
+    
+    /// An assignment to a variable, which produces
+    /// the assigned value, e.g. `x = 1`
+    /// 
+    /// `target` is the span of the variable's name.
+    Assign {
+        target: Span<'a>,
+        value: Box<Expr<'a>>
+    },
+    
+    /// A function call, e.g. `f(a, b)`
+    /// 
+    /// `span` covers the callee and the argument list.
+    Call {
+        callee: Box<Expr<'a>>,
+        args: Vec<Expr<'a>>,
+        span: Span<'a>
+    }
+}
+
+/// The operators that take a single operand
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum UnaryOp {
+    Negate,
+    
+    /// Unary `+`, which leaves a number unchanged
+    Plus,
+    
+    /// `~`, which flips every bit of an integer
+    BitNot
+}
+
+/// The operators that take two operands
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    
+    /// `++`, which joins strings or byte sequences
+    Concat,
+    
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    
+    /// `is`, which tests whether a value has the
+    /// type named by a string, e.g. `x is "int"`
+    Is
+}
+
+/// The operators that short-circuit
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LogicalOp {
+    And,
+    Or
+}
 
 /// An enum representing the possible elements
 /// 
 /// Elements produce one or more values
+// Not parsed yet
+#[allow(dead_code)]
 #[derive(Debug, PartialEq)]
 enum Elmt {}
 
 /// An enum representing the different kinds of
 /// matches that can be made in match expressions
+// Not parsed yet
+#[allow(dead_code)]
 #[derive(Debug, PartialEq)]
 enum Mtch {}
\ No newline at end of file
@@ -0,0 +1,166 @@
+//! Helpers for reporting errors against the source
+//! text
+
+/// Return the number of lines in the source
+/// 
+/// A trailing newline doesn't start another line,
+/// and empty source has no lines.
+pub fn line_count(source: &str) -> u32 {
+    let newlines = bytecount::count(source.as_bytes(), b'\n') as u32;
+    if source.is_empty() || source.ends_with('\n') {
+        newlines
+    } else {
+        newlines + 1
+    }
+}
+
+/// Return the 1-based line and column of a byte
+/// offset in the source, counted the same way as a
+/// `Span`
+/// 
+/// Columns count Unicode scalar values. An offset
+/// past the end of the source is treated as the end
+/// of the source.
+pub fn offset_to_position(source: &str, offset: usize) -> (u32, u32) {
+    let before = &source.as_bytes()[..offset.min(source.len())];
+    let line = bytecount::count(before, b'\n') as u32 + 1;
+    let line_start = memchr::memrchr(b'\n', before).map_or(0, |newline| newline + 1);
+    let column = before[line_start..].iter()
+        .filter(|&&byte| byte & 0xC0 != 0x80)
+        .count() as u32 + 1;
+    
+    (line, column)
+}
+
+/// The ANSI code for bold red text
+const RED: &str = "1;31";
+
+/// The ANSI code for bold blue text
+const BLUE: &str = "1;34";
+
+/// An error found in the source text
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    /// What went wrong
+    pub message: String,
+    
+    /// The byte offset of the start of the error
+    pub offset: usize,
+    
+    /// The length of the erroneous text in bytes
+    pub len: usize
+}
+
+/// Render an error along with the line it is on,
+/// underlining the erroneous text, e.g.
+/// 
+/// ```text
+/// error: undefined variable
+///  --> 2:5
+///   |
+/// 2 |     café()
+///   |     ^^^^
+/// ```
+/// 
+/// With `colored`, ANSI codes make `error` red and
+/// the gutter and underline blue, which should only
+/// be used when writing to a terminal.
+pub fn render_error(source: &str, diag: &Diagnostic, colored: bool) -> String {
+    let paint = |code: &str, text: &str| if colored {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text.to_string()
+    };
+    
+    let (line, column) = offset_to_position(source, diag.offset);
+    let text = source.lines().nth(line as usize - 1).unwrap_or("");
+    let underlined = source.get(diag.offset..diag.offset + diag.len)
+        .map_or(0, |slice| slice.chars().take_while(|&c| c != '\n').count());
+    let gutter = " ".repeat(line.to_string().len());
+    let bar = paint(BLUE, "|");
+    
+    format!(
+        "{}: {}\n{}{} {}:{}\n{} {}\n{} {} {}\n{} {} {}{}\n",
+        paint(RED, "error"),
+        diag.message,
+        gutter,
+        paint(BLUE, "-->"),
+        line,
+        column,
+        gutter,
+        bar,
+        paint(BLUE, &line.to_string()),
+        bar,
+        text,
+        gutter,
+        bar,
+        " ".repeat(column as usize - 1),
+        paint(BLUE, &"^".repeat(underlined.max(1)))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tokens::Span;
+    use nom::InputTake;
+    
+    const SOURCE: &str = "fn main() {\n    café()\n}\n";
+    
+    #[test]
+    fn positions_in_multiline_source() {
+        assert_eq!((1, 1), offset_to_position(SOURCE, 0));
+        assert_eq!((1, 12), offset_to_position(SOURCE, 11));
+        assert_eq!((2, 1), offset_to_position(SOURCE, 12));
+        assert_eq!((2, 9), offset_to_position(SOURCE, 21));
+        assert_eq!((3, 1), offset_to_position(SOURCE, 24));
+        assert_eq!((4, 1), offset_to_position(SOURCE, 1000));
+    }
+    
+    #[test]
+    fn matches_span() {
+        for offset in &[0, 5, 12, 19, 21, 25] {
+            let (rest, _) = Span::new(SOURCE).take_split(*offset);
+            
+            assert_eq!((rest.line, rest.column), offset_to_position(SOURCE, *offset));
+        }
+    }
+    
+    #[test]
+    fn count_lines() {
+        assert_eq!(3, line_count(SOURCE));
+        assert_eq!(2, line_count("a\nb"));
+        assert_eq!(0, line_count(""));
+    }
+    
+    /// An error pointing at `café` in `SOURCE`
+    fn cafe_error() -> Diagnostic {
+        Diagnostic {
+            message: "undefined variable".to_string(),
+            offset: 16,
+            len: "café".len()
+        }
+    }
+    
+    #[test]
+    fn plain_error() {
+        let expected = concat!(
+            "error: undefined variable\n",
+            " --> 2:5\n",
+            "  |\n",
+            "2 |     café()\n",
+            "  |     ^^^^\n"
+        );
+        
+        assert_eq!(expected, render_error(SOURCE, &cafe_error(), false));
+    }
+    
+    #[test]
+    fn colored_error() {
+        let colored = render_error(SOURCE, &cafe_error(), true);
+        
+        assert!(colored.contains("\x1b[1;31merror\x1b[0m"));
+        assert!(colored.contains("\x1b[1;34m^^^^\x1b[0m"));
+        assert!(!render_error(SOURCE, &cafe_error(), false).contains('\x1b'));
+    }
+}
\ No newline at end of file
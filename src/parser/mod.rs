@@ -1,4 +1,316 @@
 mod rules;
-mod ast;
-mod tokens;
-mod internals;
\ No newline at end of file
+pub mod ast;
+pub mod tokens;
+pub mod diagnostics;
+mod internals;
+
+use ast::{Decl, Expr};
+use tokens::Span;
+use nom::{IResult, Err, error::ErrorKind, character::complete::multispace0};
+
+/// Parse an expression from the start of `input`,
+/// returning it along with the input that is left
+/// over
+/// 
+/// A REPL can check whether the leftover input is
+/// empty to see if a line is a single complete
+/// expression, and use `is_incomplete` on a failed
+/// parse to see if the line just needs to be
+/// continued.
+pub fn parse_expr_partial(input: &str) -> IResult<Span<'_>, Expr<'_>> {
+    rules::expression(Span::new(input))
+}
+
+/// Parse a declaration from the start of `input`,
+/// returning it along with the input that is left
+/// over, like `parse_expr_partial`
+pub fn parse_decl_partial(input: &str) -> IResult<Span<'_>, Decl<'_>> {
+    rules::declaration(Span::new(input))
+}
+
+/// Parse the whole of `input` as a program, a series
+/// of declarations
+/// 
+/// Empty or whitespace-only input is an empty
+/// program. Anything after the last declaration
+/// other than whitespace and comments is an
+/// "unexpected token after end of input" error at
+/// the start of that text, and other errors are
+/// described like `parse_expr`'s.
+pub fn parse_program(input: &str) -> Result<Vec<Decl<'_>>, String> {
+    let mut decls = vec![];
+    let mut rest = Span::new(input);
+    loop {
+        if rest.as_slice().trim().is_empty() {
+            return Ok(decls);
+        }
+        match rules::declaration(rest) {
+            Ok((next, decl)) => {
+                decls.push(decl);
+                rest = next;
+            },
+            Err(Err::Error((end, _))) if end.as_slice().trim().is_empty() => return Ok(decls),
+            Err(Err::Error(_)) if !decls.is_empty() => {
+                return Err(at("unexpected token after end of input", skip_whitespace(rest)));
+            },
+            Err(error) => return Err(describe(error))
+        }
+    }
+}
+
+/// Parse the whole of `input` as one expression
+/// 
+/// Unlike `parse_expr_partial`, leftover input is an
+/// error, and a failed parse is described by a
+/// message, e.g. "unexpected `)` at line 1, column 5".
+pub fn parse_expr(input: &str) -> Result<Expr<'_>, String> {
+    if input.trim().is_empty() {
+        return Err("expected expression, found end of input".to_string());
+    }
+    match parse_expr_partial(input) {
+        Ok((rest, expr)) if rest.as_slice().trim().is_empty() => Ok(expr),
+        Ok((rest, _)) => Err(unexpected(rest)),
+        Err(error) => Err(describe(error))
+    }
+}
+
+/// Describe why a parse failed
+fn describe(error: Err<(Span, ErrorKind)>) -> String {
+    match error {
+        Err::Failure((rest, ErrorKind::TooLarge)) => at("integer literal out of range", rest),
+        Err::Failure((rest, ErrorKind::Float)) => at("float literal out of range", rest),
+        Err::Failure((rest, ErrorKind::TakeWhileMN)) => at("identifier too long", rest),
+        Err::Error((rest, _)) | Err::Failure((rest, _)) => unexpected(rest),
+        Err::Incomplete(_) => "unexpected end of input".to_string()
+    }
+}
+
+/// Describe the input that a parse stopped at
+fn unexpected(rest: Span) -> String {
+    let rest = skip_whitespace(rest);
+    match rest.as_slice().chars().next() {
+        Some(c) => at(&format!("unexpected `{}`", c), rest),
+        None => "unexpected end of input".to_string()
+    }
+}
+
+/// Return the input after any leading whitespace
+fn skip_whitespace(input: Span) -> Span {
+    multispace0::<_, (Span, ErrorKind)>(input).map_or(input, |(rest, _)| rest)
+}
+
+/// Add the position of `span` to a message
+fn at(message: &str, span: Span) -> String {
+    format!("{} at line {}, column {}", message, span.line, span.column)
+}
+
+/// Return whether a parse failed because the input
+/// ended too soon, e.g. `(1 +`, rather than because
+/// of a syntax error
+pub fn is_incomplete(error: &Err<(Span, ErrorKind)>) -> bool {
+    match error {
+        Err::Incomplete(_) => true,
+        Err::Error((rest, _)) | Err::Failure((rest, _)) => rest.as_slice().trim().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::value::Value;
+    
+    #[test]
+    fn complete_parse() {
+        let (rest, expr) = parse_expr_partial("1 + 2").unwrap();
+        
+        assert_eq!("", rest.as_slice());
+        assert!(matches!(expr, Expr::Binary { .. }));
+    }
+    
+    #[test]
+    fn partial_parse_leaves_input() {
+        let (rest, expr) = parse_expr_partial("1 )").unwrap();
+        
+        assert_eq!(" )", rest.as_slice());
+        assert_eq!(Expr::Literal(Value::Int(1)), expr);
+    }
+    
+    #[test]
+    fn incomplete_parse() {
+        for input in &["1 +", "(1 + 2", "-", "2 * (  "] {
+            let error = parse_expr_partial(input).unwrap_err();
+            
+            assert!(is_incomplete(&error), "`{}` should be incomplete", input);
+        }
+    }
+    
+    #[test]
+    fn syntax_error_is_not_incomplete() {
+        let error = parse_expr_partial("1 + )").unwrap_err();
+        
+        assert!(!is_incomplete(&error));
+    }
+    
+    #[test]
+    fn empty_expression() {
+        for input in &["", "  \n\t"] {
+            assert_eq!(
+                Err("expected expression, found end of input".to_string()),
+                parse_expr(input)
+            );
+        }
+    }
+    
+    #[test]
+    fn empty_program() {
+        for input in &["", "  \n\t"] {
+            assert_eq!(Ok(vec![]), parse_program(input));
+        }
+    }
+    
+    #[test]
+    fn program_of_declarations() {
+        let program = parse_program("fn a() {}\n\n/// Two.\nfn b(x) { x }\n").unwrap();
+        let names: Vec<&str> = program.iter()
+            .map(|Decl::Function { name, .. }| name.as_slice())
+            .collect();
+        
+        assert_eq!(vec!["a", "b"], names);
+        assert_eq!(
+            Err("unexpected `}` at line 1, column 6".to_string()),
+            parse_program("fn a(}")
+        );
+    }
+    
+    #[test]
+    fn trailing_input_after_program() {
+        assert_eq!(
+            Err("unexpected token after end of input at line 1, column 14".to_string()),
+            parse_program("fn main() {} garbage")
+        );
+        assert_eq!(
+            Err("unexpected token after end of input at line 2, column 1".to_string()),
+            parse_program("fn main() {}\n1 + 2")
+        );
+        assert_eq!(1, parse_program("fn main() {}\n// done\n").unwrap().len());
+    }
+    
+    #[test]
+    fn expression_errors() {
+        assert_eq!(Ok(Expr::Literal(Value::Int(1))), parse_expr(" 1 "));
+        assert_eq!(Err("unexpected end of input".to_string()), parse_expr("(1 +"));
+        assert_eq!(
+            Err("unexpected `)` at line 1, column 5".to_string()),
+            parse_expr("1 + )")
+        );
+        assert_eq!(
+            Err("unexpected `2` at line 2, column 1".to_string()),
+            parse_expr("1\n2")
+        );
+    }
+    
+    #[test]
+    fn out_of_range_literals() {
+        assert_eq!(
+            Err("integer literal out of range at line 1, column 5".to_string()),
+            parse_expr("1 + 99999999999999999999")
+        );
+        assert_eq!(
+            Err("float literal out of range at line 1, column 1".to_string()),
+            parse_expr("1.5e999")
+        );
+        assert_eq!(
+            Err("identifier too long at line 1, column 3".to_string()),
+            parse_expr(&format!("1+{}", "x".repeat(300)))
+        );
+    }
+    
+    #[test]
+    fn source_round_trip() {
+        let values = vec![
+            Value::Int(0),
+            Value::Int(42),
+            Value::Int(i64::MAX),
+            Value::Float(0.5),
+            Value::Float(2.0),
+            Value::Float(1e300),
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::from(""),
+            Value::from("say \"hi\" \\ o/"),
+            Value::from("tab\tnew\nline\r\0"),
+            Value::from("bell\u{7} café ☕")
+        ];
+        for value in values {
+            let source = value.to_source();
+            
+            assert_eq!(Ok(Expr::Literal(value)), parse_expr(&source), "source: {}", source);
+        }
+    }
+    
+    /// Check that `span` is the part of `source` at
+    /// its offset, line, and column, and lies within
+    /// `parent`
+    fn check_span(source: &str, span: Span, parent: Span) {
+        let end = span.offset + span.as_slice().len();
+        assert_eq!(Some(span.as_slice()), source.get(span.offset..end), "{:?} in {:?}", span, source);
+        
+        let before = &source[..span.offset];
+        let line = before.matches('\n').count() as u32 + 1;
+        let column = before.rsplit('\n').next().unwrap().chars().count() as u32 + 1;
+        assert_eq!((line, column), (span.line, span.column), "{:?} in {:?}", span, source);
+        
+        let parent_end = parent.offset + parent.as_slice().len();
+        assert!(
+            parent.offset <= span.offset && end <= parent_end,
+            "{:?} is outside {:?}", span, parent
+        );
+    }
+    
+    /// Check every span in `expr` against `source`,
+    /// where `parent` is the span of the nearest
+    /// enclosing node that has one
+    fn check_spans(source: &str, expr: &Expr, parent: Span) {
+        match expr {
+            Expr::Literal(_) => {},
+            Expr::Variable(span) => check_span(source, *span, parent),
+            Expr::Unary { operand, .. } => check_spans(source, operand, parent),
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                check_spans(source, left, parent);
+                check_spans(source, right, parent);
+            },
+            Expr::IfElse { condition, then_expr, else_expr } => {
+                check_spans(source, condition, parent);
+                check_spans(source, then_expr, parent);
+                check_spans(source, else_expr, parent);
+            },
+            Expr::Assign { target, value } => {
+                check_span(source, *target, parent);
+                check_spans(source, value, parent);
+            },
+            Expr::Call { callee, args, span } => {
+                check_span(source, *span, parent);
+                check_spans(source, callee, *span);
+                for arg in args {
+                    check_spans(source, arg, *span);
+                }
+            }
+        }
+    }
+    
+    #[test]
+    fn spans_match_source() {
+        let sources = [
+            "f(x, g(y))(z)",
+            "total = count = f(\n    a + b,\n    if ok { g(c) } else { d }\n)",
+            "  \n\t a and b(c) is d or -e",
+            "caf\u{e9} = \"\u{2615}\" ++ na\u{ef}ve(\n  \u{fc}ber, (x)( 1 )\n)",
+            "x = if f() { y = g(1)(2) } else { h(z = 3) }"
+        ];
+        for source in &sources {
+            let expr = parse_expr(source).unwrap();
+            
+            check_spans(source, &expr, Span::new(source));
+        }
+    }
+}
\ No newline at end of file
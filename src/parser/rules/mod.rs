@@ -1,23 +1,77 @@
-use crate::parser::tokens::Span;
+use crate::parser::tokens::{
+    Span,
+    KEYWORDS,
+    AMPERSAND,
+    AND,
+    CARET,
+    COMMA,
+    DOUBLE_QUOTE,
+    ELSE,
+    EQUAL,
+    FALSE,
+    FN,
+    GREATER_GREATER,
+    IF,
+    IS,
+    LEFT_BRACE,
+    LEFT_PAREN,
+    LESS_LESS,
+    MINUS,
+    OR,
+    PIPE,
+    PLUS,
+    PLUS_PLUS,
+    RIGHT_BRACE,
+    RIGHT_PAREN,
+    SLASH,
+    STAR,
+    TILDE,
+    TRUE
+};
+use crate::parser::ast::{Decl, Expr, UnaryOp, BinaryOp, LogicalOp};
+use crate::vm::value::Value;
+use std::num::IntErrorKind;
+use std::str::CharIndices;
 
 use nom::{
     IResult,
+    InputTake,
     InputTakeAtPosition,
     AsChar,
+    Err,
+    error::ErrorKind,
+    branch::alt,
     combinator::{
-        verify
+        verify,
+        map,
+        opt,
+        not,
+        cut,
+        value
     },
     sequence::{
         preceded,
+        terminated,
+        delimited,
+        tuple
     },
     character::complete::{
         multispace0,
+        digit1
+    },
+    bytes::complete::{
+        tag,
+        take_while,
+        take_while1
     },
-    bytes::complete::take_while1
+    multi::{
+        many0,
+        separated_list
+    }
 };
 
 /// Ignore any preceding whitespace
-fn ws<'a, F, I, O>(parser: F) -> impl Fn(I) -> IResult<I, O>
+fn ws<F, I, O>(parser: F) -> impl Fn(I) -> IResult<I, O>
 where F: Fn(I) -> IResult<I, O>,
       I: InputTakeAtPosition,
       <I as InputTakeAtPosition>::Item: AsChar + Clone
@@ -25,17 +79,935 @@ where F: Fn(I) -> IResult<I, O>,
     preceded(multispace0, parser)
 }
 
-/// Match any identifier
+/// Run a parser and also return the `Span` covering
+/// exactly the input it consumed
+fn spanned<'a, F, O>(parser: F) -> impl Fn(Span<'a>) -> IResult<Span<'a>, (O, Span<'a>)>
+where F: Fn(Span<'a>) -> IResult<Span<'a>, O>
+{
+    move |input: Span<'a>| {
+        let (rest, output) = parser(input)?;
+        let consumed = input.take(rest.offset - input.offset);
+        Ok((rest, (output, consumed)))
+    }
+}
+
+/// Match any `///` doc comment lines, returning
+/// their combined text, or `None` if there are none
+/// 
+/// The `///` and one space after it are removed
+/// from each line, and the lines are joined with
+/// newlines. Ordinary `//` comment lines are
+/// skipped.
+fn doc_comment(input: Span) -> IResult<Span, Option<String>> {
+    map(
+        many0(ws(alt((
+            map(preceded(tag("///"), take_while(|c: char| c != '\n')), Some),
+            value(None, preceded(tag("//"), take_while(|c: char| c != '\n')))
+        )))),
+        |lines: Vec<Option<Span>>| {
+            let lines: Vec<&str> = lines.iter()
+                .flatten()
+                .map(|line| {
+                    let line = line.as_slice();
+                    line.strip_prefix(' ').unwrap_or(line).trim_end()
+                })
+                .collect();
+            if lines.is_empty() {
+                None
+            } else {
+                Some(lines.join("\n"))
+            }
+        }
+    )(input)
+}
+
+/// The longest identifier `identifier` accepts, in
+/// characters
+pub const MAX_IDENTIFIER_LEN: usize = 255;
+
+/// Match any identifier of at most
+/// `MAX_IDENTIFIER_LEN` characters
 /// 
 /// An identifier is a sequence of characters where
 /// the first character is alphabetic or `_`
 /// character and each subsequent character is an
-/// alphanumeric character or `_`
+/// alphanumeric character or `_`. Keywords are not
+/// identifiers.
 fn identifier<'a>(input: Span<'a>) -> IResult<Span<'a>, Span<'a>> {
-    verify(
-        take_while1(|c: char| c.is_alphanumeric() || c == '_'),
-        |slice: &Span<'a>|
-            slice.as_slice().chars().nth(0).unwrap().is_alphabetic() ||
-            slice.as_slice().chars().nth(0).unwrap() == '_'
+    identifier_up_to(MAX_IDENTIFIER_LEN)(input)
+}
+
+/// Match any identifier of at most `max_len`
+/// characters
+/// 
+/// A longer identifier fails outright with
+/// `ErrorKind::TakeWhileMN` at its start, rather than
+/// letting another parser try the input. The kind
+/// differs from the `ErrorKind::TooLarge` of an
+/// out-of-range integer.
+fn identifier_up_to<'a>(max_len: usize) -> impl Fn(Span<'a>) -> IResult<Span<'a>, Span<'a>> {
+    move |input: Span<'a>| {
+        let (rest, name) = verify(
+            take_while1(|c: char| c.is_alphanumeric() || c == '_'),
+            |slice: &Span<'a>|
+                (slice.as_slice().chars().next().unwrap().is_alphabetic() ||
+                 slice.as_slice().starts_with('_')) &&
+                !KEYWORDS.contains(&slice.as_slice())
+        )(input)?;
+        if name.as_slice().chars().count() > max_len {
+            return Err(Err::Failure((name, ErrorKind::TakeWhileMN)));
+        }
+        Ok((rest, name))
+    }
+}
+
+/// Match a function declaration, e.g.
+/// `fn add(a, b) { a + b }`, along with any doc
+/// comment before it
+/// 
+/// Once `fn` has been matched, the rest of the
+/// declaration is required.
+pub fn declaration(input: Span) -> IResult<Span, Decl> {
+    let (input, doc) = doc_comment(input)?;
+    let (input, _) = ws(keyword(FN))(input)?;
+    let (input, (name, params, body)) = cut(tuple((
+        ws(identifier),
+        delimited(
+            ws(tag(LEFT_PAREN)),
+            separated_list(ws(tag(COMMA)), ws(identifier)),
+            ws(tag(RIGHT_PAREN))
+        ),
+        delimited(ws(tag(LEFT_BRACE)), opt(expression), ws(tag(RIGHT_BRACE)))
+    )))(input)?;
+    Ok((input, Decl::Function { doc, name, params, body }))
+}
+
+/// Match an expression
+/// 
+/// Once an operator or opening parenthesis has been
+/// matched, the rest of the expression is required, so
+/// input that ends early fails at the end of the input
+/// rather than backtracking.
+pub fn expression(input: Span) -> IResult<Span, Expr> {
+    assignment(input)
+}
+
+/// Match an assignment, which is right-associative
+/// and binds more loosely than any operator, e.g.
+/// `x = y = 1`
+/// 
+/// Only a variable can be assigned to, so any other
+/// expression followed by `=` fails.
+fn assignment(input: Span) -> IResult<Span, Expr> {
+    let (rest, target) = logical_or(input)?;
+    match ws(tag(EQUAL))(rest) {
+        Ok((after_equal, _)) => match target {
+            Expr::Variable(target) => {
+                let (rest, value) = cut(assignment)(after_equal)?;
+                Ok((rest, Expr::Assign { target, value: Box::new(value) }))
+            },
+            _ => Err(Err::Failure((rest, ErrorKind::Verify)))
+        },
+        Err(Err::Error(_)) => Ok((rest, target)),
+        Err(error) => Err(error)
+    }
+}
+
+/// Match logical or
+fn logical_or(input: Span) -> IResult<Span, Expr> {
+    logical(logical_and, value(LogicalOp::Or, keyword(OR)))(input)
+}
+
+/// Match logical and
+fn logical_and(input: Span) -> IResult<Span, Expr> {
+    logical(type_test, value(LogicalOp::And, keyword(AND)))(input)
+}
+
+/// Match a type test, e.g. `x is "int"`
+fn type_test(input: Span) -> IResult<Span, Expr> {
+    binary(bit_or, value(BinaryOp::Is, keyword(IS)))(input)
+}
+
+/// Match bitwise or
+fn bit_or(input: Span) -> IResult<Span, Expr> {
+    binary(bit_xor, value(BinaryOp::BitOr, tag(PIPE)))(input)
+}
+
+/// Match bitwise exclusive or
+fn bit_xor(input: Span) -> IResult<Span, Expr> {
+    binary(bit_and, value(BinaryOp::BitXor, tag(CARET)))(input)
+}
+
+/// Match bitwise and
+fn bit_and(input: Span) -> IResult<Span, Expr> {
+    binary(shift, value(BinaryOp::BitAnd, tag(AMPERSAND)))(input)
+}
+
+/// Match left and right shifts
+fn shift(input: Span) -> IResult<Span, Expr> {
+    binary(term, alt((
+        value(BinaryOp::ShiftLeft, tag(LESS_LESS)),
+        value(BinaryOp::ShiftRight, tag(GREATER_GREATER))
+    )))(input)
+}
+
+/// Match addition, concatenation, and subtraction
+fn term(input: Span) -> IResult<Span, Expr> {
+    binary(factor, alt((
+        value(BinaryOp::Concat, tag(PLUS_PLUS)),
+        value(BinaryOp::Add, tag(PLUS)),
+        value(BinaryOp::Subtract, tag(MINUS))
+    )))(input)
+}
+
+/// Match multiplication and division
+fn factor(input: Span) -> IResult<Span, Expr> {
+    binary(unary, alt((
+        value(BinaryOp::Multiply, tag(STAR)),
+        value(BinaryOp::Divide, tag(SLASH))
+    )))(input)
+}
+
+/// Match a prefix `-`, `+`, or `~`, or a call
+fn unary(input: Span) -> IResult<Span, Expr> {
+    let operator = alt((
+        value(UnaryOp::Negate, tag(MINUS)),
+        value(UnaryOp::Plus, tag(PLUS)),
+        value(UnaryOp::BitNot, tag(TILDE))
+    ));
+    
+    alt((
+        map(
+            tuple((ws(operator), cut(unary))),
+            |(op, operand)| Expr::Unary { op, operand: Box::new(operand) }
+        ),
+        call
+    ))(input)
+}
+
+/// Match a primary expression followed by any
+/// number of argument lists, e.g. `f(1, 2)` or
+/// `f(1)(2)`
+/// 
+/// Each call's span runs from the start of the
+/// callee to its closing parenthesis.
+fn call(input: Span) -> IResult<Span, Expr> {
+    let (input, _) = multispace0(input)?;
+    let (rest, ((callee, arg_lists), whole)) =
+        spanned(tuple((primary, many0(spanned(arguments)))))(input)?;
+    let call = arg_lists.into_iter().fold(callee, |callee, (args, parens)| Expr::Call {
+        callee: Box::new(callee),
+        args,
+        span: whole.take(parens.offset + parens.as_slice().len() - whole.offset)
+    });
+    Ok((rest, call))
+}
+
+/// Match a parenthesized, comma-separated list of
+/// arguments, e.g. `(1, 2)`
+fn arguments(input: Span) -> IResult<Span, Vec<Expr>> {
+    preceded(
+        ws(tag(LEFT_PAREN)),
+        cut(terminated(
+            separated_list(ws(tag(COMMA)), expression),
+            ws(tag(RIGHT_PAREN))
+        ))
+    )(input)
+}
+
+/// Match a literal, a variable, a parenthesized
+/// expression, or an `if` expression
+fn primary(input: Span) -> IResult<Span, Expr> {
+    alt((
+        map(ws(number), Expr::Literal),
+        map(ws(boolean), Expr::Literal),
+        map(ws(string), Expr::Literal),
+        map(ws(identifier), Expr::Variable),
+        if_else,
+        delimited(
+            ws(tag(LEFT_PAREN)),
+            cut(expression),
+            cut(ws(tag(RIGHT_PAREN)))
+        )
+    ))(input)
+}
+
+/// Match an `if` expression, which must have an
+/// `else` branch
+fn if_else(input: Span) -> IResult<Span, Expr> {
+    map(
+        preceded(
+            ws(keyword(IF)),
+            cut(tuple((
+                expression,
+                block,
+                preceded(ws(keyword(ELSE)), block)
+            )))
+        ),
+        |(condition, then_expr, else_expr)| Expr::IfElse {
+            condition: Box::new(condition),
+            then_expr: Box::new(then_expr),
+            else_expr: Box::new(else_expr)
+        }
+    )(input)
+}
+
+/// Match an expression surrounded by braces
+fn block(input: Span) -> IResult<Span, Expr> {
+    delimited(
+        ws(tag(LEFT_BRACE)),
+        cut(expression),
+        cut(ws(tag(RIGHT_BRACE)))
     )(input)
 }
+
+/// Match `true` or `false`
+fn boolean(input: Span) -> IResult<Span, Value> {
+    alt((
+        value(Value::Bool(true), keyword(TRUE)),
+        value(Value::Bool(false), keyword(FALSE))
+    ))(input)
+}
+
+/// Match a string literal in double quotes
+/// 
+/// The escapes `\"`, `\\`, `\n`, `\r`, `\t`, `\0`, and
+/// `\u{...}` with a hexadecimal code point are
+/// recognized. An unknown escape or a string that
+/// isn't closed fails outright.
+fn string(input: Span) -> IResult<Span, Value> {
+    let (rest, _) = tag(DOUBLE_QUOTE)(input)?;
+    let body = rest.as_slice();
+    let mut text = String::new();
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((rest.take_split(i + 1).0, Value::Str(text))),
+            '\\' => {
+                let escaped = match chars.next() {
+                    Some((_, '"')) => Some('"'),
+                    Some((_, '\\')) => Some('\\'),
+                    Some((_, 'n')) => Some('\n'),
+                    Some((_, 'r')) => Some('\r'),
+                    Some((_, 't')) => Some('\t'),
+                    Some((_, '0')) => Some('\0'),
+                    Some((_, 'u')) => unicode_escape(&mut chars),
+                    _ => None
+                };
+                match escaped {
+                    Some(escaped) => text.push(escaped),
+                    None => return Err(Err::Failure((rest.take_split(i).0, ErrorKind::Escaped)))
+                }
+            },
+            c => text.push(c)
+        }
+    }
+    Err(Err::Failure((rest.take_split(body.len()).0, ErrorKind::Eof)))
+}
+
+/// Read the `{...}` of a `\u{...}` escape, returning
+/// the character with that hexadecimal code point
+fn unicode_escape(chars: &mut CharIndices) -> Option<char> {
+    if chars.next()?.1 != '{' {
+        return None;
+    }
+    let mut digits = String::new();
+    loop {
+        match chars.next()?.1 {
+            '}' => break,
+            c => digits.push(c)
+        }
+    }
+    u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32)
+}
+
+/// Match a keyword that isn't the start of a
+/// longer identifier, e.g. `if` but not `iffy`
+fn keyword<'a>(word: &'static str) -> impl Fn(Span<'a>) -> IResult<Span<'a>, Span<'a>> {
+    terminated(
+        tag(word),
+        not(take_while1(|c: char| c.is_alphanumeric() || c == '_'))
+    )
+}
+
+/// Match a left-associative chain of operands
+/// separated by operators, e.g. `a - b - c`
+fn binary<'a, P, O>(operand: P, operator: O)
+    -> impl Fn(Span<'a>) -> IResult<Span<'a>, Expr<'a>>
+where P: Fn(Span<'a>) -> IResult<Span<'a>, Expr<'a>>,
+      O: Fn(Span<'a>) -> IResult<Span<'a>, BinaryOp>
+{
+    chain(operand, operator, |left, op, right| Expr::Binary {
+        left: Box::new(left),
+        op,
+        right: Box::new(right)
+    })
+}
+
+/// Match a left-associative chain of operands
+/// separated by logical operators, e.g. `a or b or c`
+fn logical<'a, P, O>(operand: P, operator: O)
+    -> impl Fn(Span<'a>) -> IResult<Span<'a>, Expr<'a>>
+where P: Fn(Span<'a>) -> IResult<Span<'a>, Expr<'a>>,
+      O: Fn(Span<'a>) -> IResult<Span<'a>, LogicalOp>
+{
+    chain(operand, operator, |left, op, right| Expr::Logical {
+        left: Box::new(left),
+        op,
+        right: Box::new(right)
+    })
+}
+
+/// Match a left-associative chain of operands
+/// separated by operators, combining each pair with
+/// `build`
+fn chain<'a, P, O, T, B>(operand: P, operator: O, build: B)
+    -> impl Fn(Span<'a>) -> IResult<Span<'a>, Expr<'a>>
+where P: Fn(Span<'a>) -> IResult<Span<'a>, Expr<'a>>,
+      O: Fn(Span<'a>) -> IResult<Span<'a>, T>,
+      B: Fn(Expr<'a>, T, Expr<'a>) -> Expr<'a>
+{
+    move |input| {
+        let (mut input, mut left) = operand(input)?;
+        loop {
+            match ws(&operator)(input) {
+                Ok((rest, op)) => {
+                    let (rest, right) = cut(&operand)(rest)?;
+                    left = build(left, op, right);
+                    input = rest;
+                },
+                Err(Err::Error(_)) => return Ok((input, left)),
+                Err(error) => return Err(error)
+            }
+        }
+    }
+}
+
+/// Match a number literal
+/// 
+/// Integers are written in decimal, or in hexadecimal,
+/// binary, or octal with a `0x`, `0b`, or `0o` prefix.
+/// Underscores may be used to separate digits and are
+/// ignored, e.g. `1_000` or `0xFF_FF`.
+/// 
+/// Floats are written in decimal with a fractional
+/// part, an exponent, or both, e.g. `1.5`, `2E-3`, or
+/// `1.5e10`.
+fn number(input: Span) -> IResult<Span, Value> {
+    alt((
+        radix_number("0x", 16),
+        radix_number("0b", 2),
+        radix_number("0o", 8),
+        float,
+        radix_number("", 10)
+    ))(input)
+}
+
+/// Match a float literal
+/// 
+/// The literal can't be directly followed by an
+/// alphanumeric character so that malformed exponents
+/// like `1.5e` or `1e+` are rejected. A literal too
+/// large to be a finite float, e.g. `1e999`, fails
+/// outright with `ErrorKind::Float`.
+fn float(input: Span) -> IResult<Span, Value> {
+    let (rest, (whole, fraction, exponent)) = terminated(
+        verify(
+            tuple((digit1, opt(preceded(tag("."), digit1)), opt(exponent))),
+            |(_, fraction, exponent): &(Span, Option<Span>, Option<String>)|
+                fraction.is_some() || exponent.is_some()
+        ),
+        not(take_while1(|c: char| c.is_alphanumeric() || c == '_'))
+    )(input)?;
+    
+    let text = format!(
+        "{}.{}{}",
+        whole.as_slice(),
+        fraction.map_or("0", |fraction| fraction.as_slice()),
+        exponent.unwrap_or_default()
+    );
+    match text.parse::<f64>() {
+        Ok(x) if x.is_finite() => Ok((rest, Value::Float(x))),
+        Ok(_) => Err(Err::Failure((input, ErrorKind::Float))),
+        Err(_) => Err(Err::Error((input, ErrorKind::Float)))
+    }
+}
+
+/// Match the exponent of a float, e.g. the `e-3`
+/// in `2e-3`, normalizing it for Rust's float parser
+fn exponent(input: Span) -> IResult<Span, String> {
+    map(
+        tuple((
+            alt((tag("e"), tag("E"))),
+            opt(alt((tag("+"), tag("-")))),
+            digit1
+        )),
+        |(_, sign, digits): (Span, Option<Span>, Span)| format!(
+            "e{}{}",
+            sign.map_or("", |sign| sign.as_slice()),
+            digits.as_slice()
+        )
+    )(input)
+}
+
+/// Match an integer with the given prefix and radix
+/// 
+/// Any alphanumeric character is taken as part of the
+/// number so that a digit that is invalid for the
+/// radix, e.g. the `2` in `0b102`, makes the whole
+/// number fail rather than being left for the next
+/// parser. Likewise, the number can't be followed by
+/// a fractional part, which would make it a
+/// (possibly malformed) float. A number too large for
+/// an `Int` fails outright with `ErrorKind::TooLarge`.
+fn radix_number<'a>(prefix: &'static str, radix: u32)
+    -> impl Fn(Span<'a>) -> IResult<Span<'a>, Value> {
+    move |input: Span<'a>| {
+        let (rest, digits) = terminated(
+            preceded(
+                tag(prefix),
+                take_while1(|c: char| c.is_alphanumeric() || c == '_')
+            ),
+            not(preceded(tag("."), digit1))
+        )(input)?;
+        
+        let digits = digits.as_slice();
+        if !digits.starts_with(|c: char| c.is_digit(radix)) {
+            return Err(Err::Error((input, ErrorKind::Digit)));
+        }
+        
+        let digits: String = digits.chars().filter(|&c| c != '_').collect();
+        match i64::from_str_radix(&digits, radix) {
+            Ok(i) => Ok((rest, Value::Int(i))),
+            Err(error) if *error.kind() == IntErrorKind::PosOverflow => {
+                Err(Err::Failure((input, ErrorKind::TooLarge)))
+            },
+            Err(_) => Err(Err::Error((input, ErrorKind::Digit)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tokens::OPERATORS;
+    
+    #[test]
+    fn keywords_are_not_identifiers() {
+        for keyword in KEYWORDS {
+            assert!(
+                identifier(Span::new(keyword)).is_err(),
+                "keyword `{}` was parsed as an identifier",
+                keyword
+            );
+        }
+    }
+    
+    #[test]
+    fn keyword_prefixed_identifiers() {
+        for keyword in KEYWORDS {
+            let input = format!("{}_x", keyword);
+            let (rest, ident) = identifier(Span::new(&input)).unwrap();
+            
+            assert_eq!(input, ident.as_slice());
+            assert_eq!("", rest.as_slice());
+        }
+    }
+    
+    #[test]
+    fn operators_are_not_identifiers() {
+        for operator in OPERATORS {
+            assert!(
+                identifier(Span::new(operator)).is_err(),
+                "operator `{}` was parsed as an identifier",
+                operator
+            );
+            
+            let input = format!("x{}", operator);
+            let (rest, ident) = identifier(Span::new(&input)).unwrap();
+            
+            assert_eq!("x", ident.as_slice());
+            assert_eq!(*operator, rest.as_slice());
+        }
+    }
+    
+    #[test]
+    fn decimal_number() {
+        let (rest, value) = number(Span::new("42 ")).unwrap();
+        
+        assert_eq!(Value::Int(42), value);
+        assert_eq!(" ", rest.as_slice());
+    }
+    
+    #[test]
+    fn radix_numbers() {
+        assert_eq!(Value::Int(255), number(Span::new("0xFF")).unwrap().1);
+        assert_eq!(Value::Int(10), number(Span::new("0b1010")).unwrap().1);
+        assert_eq!(Value::Int(15), number(Span::new("0o17")).unwrap().1);
+    }
+    
+    #[test]
+    fn number_with_separators() {
+        assert_eq!(Value::Int(1000), number(Span::new("1_000")).unwrap().1);
+        assert_eq!(Value::Int(0xFFFF), number(Span::new("0xFF_FF")).unwrap().1);
+    }
+    
+    #[test]
+    fn invalid_digit_for_radix() {
+        assert!(number(Span::new("0b102")).is_err());
+        assert!(number(Span::new("0o8")).is_err());
+        assert!(number(Span::new("0xG")).is_err());
+    }
+    
+    #[test]
+    fn float_number() {
+        assert_eq!(Value::Float(1.5), number(Span::new("1.5")).unwrap().1);
+    }
+    
+    #[test]
+    fn scientific_float_numbers() {
+        assert_eq!(Value::Float(1.5e10), number(Span::new("1.5e10")).unwrap().1);
+        assert_eq!(Value::Float(2e-3), number(Span::new("2E-3")).unwrap().1);
+        assert_eq!(Value::Float(1e5), number(Span::new("1e5")).unwrap().1);
+        assert_eq!(Value::Float(1e5), number(Span::new("1e+5")).unwrap().1);
+    }
+    
+    #[test]
+    fn malformed_exponent() {
+        assert!(number(Span::new("1e")).is_err());
+        assert!(number(Span::new("1e+")).is_err());
+        assert!(number(Span::new("1.5e")).is_err());
+    }
+    
+    #[test]
+    fn identifier_is_not_a_number() {
+        assert!(number(Span::new("_1")).is_err());
+        assert!(number(Span::new("abc")).is_err());
+    }
+    
+    /// Shorthand for a literal integer expression
+    fn int(i: i64) -> Box<Expr<'static>> {
+        Box::new(Expr::Literal(Value::Int(i)))
+    }
+    
+    #[test]
+    fn arithmetic_precedence() {
+        let expected = Expr::Binary {
+            left: int(1),
+            op: BinaryOp::Add,
+            right: Box::new(Expr::Binary {
+                left: int(2),
+                op: BinaryOp::Multiply,
+                right: Box::new(Expr::Unary { op: UnaryOp::Negate, operand: int(3) })
+            })
+        };
+        
+        assert_eq!(expected, expression(Span::new("1 + 2 * -3")).unwrap().1);
+    }
+    
+    #[test]
+    fn left_associative() {
+        let expected = Expr::Binary {
+            left: Box::new(Expr::Binary { left: int(1), op: BinaryOp::Subtract, right: int(2) }),
+            op: BinaryOp::Subtract,
+            right: int(3)
+        };
+        
+        assert_eq!(expected, expression(Span::new("1 - 2 - 3")).unwrap().1);
+    }
+    
+    #[test]
+    fn grouping() {
+        let expected = Expr::Binary {
+            left: Box::new(Expr::Binary { left: int(1), op: BinaryOp::Add, right: int(2) }),
+            op: BinaryOp::Multiply,
+            right: int(3)
+        };
+        
+        assert_eq!(expected, expression(Span::new("(1 + 2) * 3")).unwrap().1);
+    }
+    
+    #[test]
+    fn unary_plus() {
+        let expected = Expr::Binary {
+            left: Box::new(Expr::Unary { op: UnaryOp::Plus, operand: int(1) }),
+            op: BinaryOp::Subtract,
+            right: Box::new(Expr::Unary { op: UnaryOp::Plus, operand: int(2) })
+        };
+        
+        assert_eq!(expected, expression(Span::new("+1 - +2")).unwrap().1);
+    }
+    
+    #[test]
+    fn concatenation() {
+        let expected = Expr::Binary {
+            left: Box::new(Expr::Binary { left: int(1), op: BinaryOp::Concat, right: int(2) }),
+            op: BinaryOp::Add,
+            right: Box::new(Expr::Unary { op: UnaryOp::Plus, operand: int(3) })
+        };
+        
+        assert_eq!(expected, expression(Span::new("1 ++ 2 + +3")).unwrap().1);
+    }
+    
+    #[test]
+    fn spanned_identifier() {
+        let (rest, (name, span)) = spanned(identifier)(Span::new("abc def")).unwrap();
+        
+        assert_eq!("abc", name.as_slice());
+        assert_eq!((0, 3), (span.offset, span.as_slice().len()));
+        
+        let (_, (name, span)) = spanned(ws(identifier))(rest).unwrap();
+        
+        assert_eq!("def", name.as_slice());
+        assert_eq!((3, " def"), (span.offset, span.as_slice()));
+        assert_eq!((1, 4), (span.line, span.column));
+    }
+    
+    #[test]
+    fn if_else_expression() {
+        let expected = Expr::Binary {
+            left: Box::new(Expr::IfElse {
+                condition: Box::new(Expr::Literal(Value::Bool(true))),
+                then_expr: int(1),
+                else_expr: int(2)
+            }),
+            op: BinaryOp::Add,
+            right: int(3)
+        };
+        
+        assert_eq!(expected, expression(Span::new("if true { 1 } else { 2 } + 3")).unwrap().1);
+    }
+    
+    #[test]
+    fn if_without_else() {
+        assert!(expression(Span::new("if true { 1 }")).is_err());
+    }
+    
+    #[test]
+    fn keyword_prefix_is_not_keyword() {
+        assert_eq!(
+            Expr::Variable(Span::new("trueish")),
+            expression(Span::new("trueish")).unwrap().1
+        );
+        assert!(identifier(Span::new("iffy")).is_ok());
+    }
+    
+    #[test]
+    fn bitwise_precedence() {
+        let expected = Expr::Binary {
+            left: int(1),
+            op: BinaryOp::BitOr,
+            right: Box::new(Expr::Binary {
+                left: Box::new(Expr::Binary {
+                    left: int(6),
+                    op: BinaryOp::BitAnd,
+                    right: Box::new(Expr::Binary {
+                        left: int(3),
+                        op: BinaryOp::ShiftLeft,
+                        right: Box::new(Expr::Binary { left: int(1), op: BinaryOp::Add, right: int(1) })
+                    })
+                }),
+                op: BinaryOp::BitXor,
+                right: Box::new(Expr::Unary { op: UnaryOp::BitNot, operand: int(2) })
+            })
+        };
+        
+        assert_eq!(expected, expression(Span::new("1 | 6 & 3 << 1 + 1 ^ ~2")).unwrap().1);
+    }
+    
+    #[test]
+    fn doc_comment_lines() {
+        let input = Span::new("/// Add one.\n///\n/// Overflow is an error.\nfn");
+        let (rest, doc) = doc_comment(input).unwrap();
+        
+        assert_eq!(Some("Add one.\n\nOverflow is an error.".to_string()), doc);
+        assert_eq!("\nfn", rest.as_slice());
+    }
+    
+    #[test]
+    fn no_doc_comment() {
+        assert_eq!(None, doc_comment(Span::new("// not a doc\nfn")).unwrap().1);
+    }
+    
+    #[test]
+    fn documented_function() {
+        let input = "/// Add one to `x`.\n// Not part of the doc.\n/// Overflow is an error.\nfn inc(x) { x + 1 }";
+        let expected = Decl::Function {
+            doc: Some("Add one to `x`.\nOverflow is an error.".to_string()),
+            name: Span::new_at("inc", 73, 4, 4),
+            params: vec![Span::new_at("x", 77, 4, 8)],
+            body: Some(Expr::Binary {
+                left: Box::new(Expr::Variable(Span::new_at("x", 82, 4, 13))),
+                op: BinaryOp::Add,
+                right: int(1)
+            })
+        };
+        
+        assert_eq!(expected, declaration(Span::new(input)).unwrap().1);
+    }
+    
+    #[test]
+    fn undocumented_function() {
+        match declaration(Span::new("// A comment.\nfn f(a, b) {}")) {
+            Ok((_, Decl::Function { doc: None, params, body: None, .. })) => assert_eq!(2, params.len()),
+            other => panic!("expected an empty function, got {:?}", other)
+        }
+        assert!(matches!(declaration(Span::new("fnord()")), Err(Err::Error(_))));
+        assert!(matches!(declaration(Span::new("fn f(1) {}")), Err(Err::Failure(_))));
+    }
+    
+    #[test]
+    fn call_arguments() {
+        let expected = Expr::Binary {
+            left: Box::new(Expr::Call {
+                callee: int(1),
+                args: vec![*int(2), *int(3)],
+                span: at("1(2, 3)", 0)
+            }),
+            op: BinaryOp::Add,
+            right: Box::new(Expr::Call { callee: int(4), args: vec![], span: at("4()", 10) })
+        };
+        
+        assert_eq!(expected, expression(Span::new("1(2, 3) + 4()")).unwrap().1);
+    }
+    
+    #[test]
+    fn chained_calls() {
+        let expected = Expr::Call {
+            callee: Box::new(Expr::Call { callee: int(1), args: vec![*int(2)], span: at("1(2)", 0) }),
+            args: vec![*int(3)],
+            span: at("1(2)(3)", 0)
+        };
+        
+        assert_eq!(expected, expression(Span::new("1(2)(3)")).unwrap().1);
+    }
+    
+    #[test]
+    fn unclosed_call() {
+        assert!(matches!(expression(Span::new("1(2, 3")), Err(Err::Failure(_))));
+    }
+    
+    /// Shorthand for a span of `slice` at `offset` on
+    /// the first line
+    fn at(slice: &str, offset: usize) -> Span<'_> {
+        Span::new_at(slice, offset, 1, offset as u32 + 1)
+    }
+    
+    /// Shorthand for a variable expression at `offset`
+    /// on the first line
+    fn var(name: &str, offset: usize) -> Box<Expr<'_>> {
+        Box::new(Expr::Variable(at(name, offset)))
+    }
+    
+    #[test]
+    fn variables() {
+        let expected = Expr::Binary { left: var("foo", 0), op: BinaryOp::Add, right: var("bar", 6) };
+        
+        assert_eq!(expected, expression(Span::new("foo + bar")).unwrap().1);
+    }
+    
+    #[test]
+    fn call_variable() {
+        let expected = Expr::Call {
+            callee: var("f", 0),
+            args: vec![*int(1), *var("x", 5)],
+            span: at("f(1, x)", 0)
+        };
+        
+        assert_eq!(expected, expression(Span::new("f(1, x)")).unwrap().1);
+    }
+    
+    #[test]
+    fn right_associative_assignment() {
+        let expected = Expr::Assign {
+            target: Span::new("x"),
+            value: Box::new(Expr::Assign {
+                target: Span::new_at("y", 4, 1, 5),
+                value: Box::new(Expr::Binary { left: int(1), op: BinaryOp::BitOr, right: int(2) })
+            })
+        };
+        
+        assert_eq!(expected, expression(Span::new("x = y = 1 | 2")).unwrap().1);
+    }
+    
+    #[test]
+    fn invalid_assignment_target() {
+        assert!(matches!(expression(Span::new("1 = 2")), Err(Err::Failure(_))));
+        assert!(matches!(expression(Span::new("x + y = 2")), Err(Err::Failure(_))));
+    }
+    
+    #[test]
+    fn type_test_precedence() {
+        let expected = Expr::Binary {
+            left: Box::new(Expr::Binary { left: var("x", 0), op: BinaryOp::BitOr, right: int(1) }),
+            op: BinaryOp::Is,
+            right: Box::new(Expr::Literal(Value::from("int")))
+        };
+        
+        assert_eq!(expected, expression(Span::new("x | 1 is \"int\"")).unwrap().1);
+        assert_eq!(
+            Expr::Binary { left: int(1), op: BinaryOp::Is, right: Box::new(Expr::Literal(Value::from("int"))) },
+            expression(Span::new("1 is \"int\"")).unwrap().1
+        );
+        assert!(expression(Span::new("x is")).is_err());
+    }
+    
+    #[test]
+    fn logical_precedence() {
+        let expected = Expr::Logical {
+            left: Box::new(Expr::Logical { left: var("a", 0), op: LogicalOp::And, right: var("b", 6) }),
+            op: LogicalOp::Or,
+            right: Box::new(Expr::Logical {
+                left: Box::new(Expr::Binary { left: var("c", 11), op: BinaryOp::Is, right: var("d", 16) }),
+                op: LogicalOp::And,
+                right: var("e", 22)
+            })
+        };
+        
+        assert_eq!(expected, expression(Span::new("a and b or c is d and e")).unwrap().1);
+    }
+    
+    #[test]
+    fn identifier_length_limit() {
+        let longest = "a".repeat(MAX_IDENTIFIER_LEN);
+        let too_long = format!(" {}b", longest);
+        
+        assert_eq!(longest, identifier(Span::new(&longest)).unwrap().1.as_slice());
+        match expression(Span::new(&too_long)) {
+            Err(Err::Failure((at, ErrorKind::TakeWhileMN))) => assert_eq!((1, 2), (at.line, at.column)),
+            other => panic!("expected the identifier to be too long, got {:?}", other)
+        }
+        assert!(identifier_up_to(3)(Span::new("abc")).is_ok());
+        assert!(identifier_up_to(3)(Span::new("abcd")).is_err());
+    }
+    
+    #[test]
+    fn out_of_range_numbers() {
+        for (input, kind) in &[
+            ("99999999999999999999", ErrorKind::TooLarge),
+            ("0xFFFF_FFFF_FFFF_FFFF", ErrorKind::TooLarge),
+            ("1e999", ErrorKind::Float)
+        ] {
+            match number(Span::new(input)) {
+                Err(Err::Failure((at, error))) => assert_eq!((0, *kind), (at.offset, error)),
+                other => panic!("expected `{}` to be out of range, got {:?}", input, other)
+            }
+        }
+        assert_eq!(Value::Int(i64::MAX), number(Span::new("9223372036854775807")).unwrap().1);
+    }
+    
+    #[test]
+    fn string_escapes() {
+        let (rest, value) = string(Span::new(r#""a \"b\" \\ \n\u{e9}" + 1"#)).unwrap();
+        
+        assert_eq!(Value::from("a \"b\" \\ \n\u{e9}"), value);
+        assert_eq!(" + 1", rest.as_slice());
+    }
+    
+    #[test]
+    fn malformed_strings() {
+        assert!(matches!(string(Span::new(r#""\q""#)), Err(Err::Failure(_))));
+        assert!(matches!(string(Span::new(r#""\u{110000}""#)), Err(Err::Failure(_))));
+        assert!(matches!(string(Span::new(r#""open"#)), Err(Err::Failure(_))));
+    }
+}
\ No newline at end of file
@@ -8,12 +8,14 @@
 //! to the lexeme itself.
 
 use crate::parser::internals::Input;
+use std::ops::RangeTo;
 use nom::{
     Compare,
     CompareResult,
     InputTake,
     InputLength,
     InputIter,
+    Slice,
     UnspecializedInput
 };
 
@@ -23,16 +25,28 @@ use nom::{
 /// 
 /// The following example declares the `EXAMPLE` token
 /// 
-/// ```
-/// token!(EXAMPLE: b"example");
+/// ```ignore
+/// token!(EXAMPLE: "example"; "The EXAMPLE token.");
 /// ```
 macro_rules! token {
     ($name:ident: $value:expr; $documentation:expr) => {
         #[doc=$documentation]
-        const $name: &'static str = $value;
+        pub const $name: &'static str = $value;
     };
 }
 
+token!(
+    AMPERSAND: "&";
+    "The AMPERSAND token.\n\nRepresent bitwise and, e.g. `a & b`"
+);
+token!(
+    AND: "and";
+    "The AND token.\n\nRepresent a short-circuiting logical and, e.g. `a and b`"
+);
+token!(
+    CARET: "^";
+    "The CARET token.\n\nRepresent bitwise exclusive or, e.g. `a ^ b`"
+);
 token!(
     COLON: ":";
     "The COLON token.\n\nRepresent the break between identifier and type declaration, eg. `count: Int`"
@@ -41,14 +55,42 @@ token!(
     COMMA: ",";
     "The COMMA token.\n\nRepresent a separator in a sequence, e.g. `(1, 2, 3)`"
 );
+token!(
+    DOUBLE_QUOTE: "\"";
+    "The DOUBLE_QUOTE token.\n\nRepresent the start or end of a string, e.g. `\"hello\"`"
+);
+token!(
+    ELSE: "else";
+    "The ELSE token.\n\nRepresent the alternative branch of an `if`, e.g. `if a { b } else { c }`"
+);
+token!(
+    EQUAL: "=";
+    "The EQUAL token.\n\nRepresent assignment, e.g. `x = 1`"
+);
+token!(
+    FALSE: "false";
+    "The FALSE token.\n\nRepresent the boolean literal `false`"
+);
 token!(
     FN: "fn";
     "The FN token.\n\nRepresent the declaration of a function, e.g. `fn foo() {}`"
 );
+token!(
+    GREATER_GREATER: ">>";
+    "The GREATER_GREATER token.\n\nRepresent a right shift, e.g. `a >> 2`"
+);
+token!(
+    IF: "if";
+    "The IF token.\n\nRepresent a conditional, e.g. `if a { b } else { c }`"
+);
 token!(
     IMPURE: "impure";
     "The IMPURE token.\n\nRepresent an impure function, e.g. `impure fn foo() {}`"
 );
+token!(
+    IS: "is";
+    "The IS token.\n\nRepresent a type test, e.g. `x is \"int\"`"
+);
 token!(
     LEFT_BRACE: "{";
     "The LEFT_BRACE token.\n\nRepresent the beginning of a code block, map, or struct, e.g. `{ ... }`"
@@ -57,6 +99,30 @@ token!(
     LEFT_PAREN: "(";
     "The LEFT_PAREN token.\n\nRepresent the beginning of a grouping or tuple, e.g. `(a, b)`"
 );
+token!(
+    LESS_LESS: "<<";
+    "The LESS_LESS token.\n\nRepresent a left shift, e.g. `a << 2`"
+);
+token!(
+    MINUS: "-";
+    "The MINUS token.\n\nRepresent subtraction or negation, e.g. `a - b` or `-a`"
+);
+token!(
+    OR: "or";
+    "The OR token.\n\nRepresent a short-circuiting logical or, e.g. `a or b`"
+);
+token!(
+    PIPE: "|";
+    "The PIPE token.\n\nRepresent bitwise or, e.g. `a | b`"
+);
+token!(
+    PLUS: "+";
+    "The PLUS token.\n\nRepresent addition, e.g. `a + b`"
+);
+token!(
+    PLUS_PLUS: "++";
+    "The PLUS_PLUS token.\n\nRepresent concatenation, e.g. `a ++ b`"
+);
 token!(
     RETURN_ARROW: "->";
     "The RETURN_ARROW token.\n\nRepresent the return type of a function, e.g. `fn a() -> Int {}`"
@@ -69,8 +135,64 @@ token!(
     RIGHT_PAREN: ")";
     "The RIGHT_PAREN token.\n\nRepresent the closing of a grouping or tuple, e.g. `(a, b)`"
 );
+token!(
+    SLASH: "/";
+    "The SLASH token.\n\nRepresent division, e.g. `a / b`"
+);
+token!(
+    STAR: "*";
+    "The STAR token.\n\nRepresent multiplication, e.g. `a * b`"
+);
+token!(
+    TILDE: "~";
+    "The TILDE token.\n\nRepresent bitwise not, e.g. `~a`"
+);
+token!(
+    TRUE: "true";
+    "The TRUE token.\n\nRepresent the boolean literal `true`"
+);
+
+/// Every keyword, none of which can be used as an
+/// identifier
+pub const KEYWORDS: &[&str] = &[
+    AND,
+    ELSE,
+    FALSE,
+    FN,
+    IF,
+    IMPURE,
+    IS,
+    OR,
+    TRUE
+];
+
+/// Every operator and punctuation lexeme
+pub const OPERATORS: &[&str] = &[
+    AMPERSAND,
+    CARET,
+    COLON,
+    COMMA,
+    DOUBLE_QUOTE,
+    EQUAL,
+    GREATER_GREATER,
+    LEFT_BRACE,
+    LEFT_PAREN,
+    LESS_LESS,
+    MINUS,
+    PIPE,
+    PLUS,
+    PLUS_PLUS,
+    RETURN_ARROW,
+    RIGHT_BRACE,
+    RIGHT_PAREN,
+    SLASH,
+    STAR,
+    TILDE
+];
 
 /// A structure pairing data with metadata
+// Unused until the parser has a separate lexer
+#[allow(dead_code)]
 struct Token<'a, T> {
     /// The value of the token
     value: T,
@@ -89,6 +211,7 @@ impl<'a, T> Token<'a, T> {
     /// 
     /// let token = Token::new(1, Span::new(b"1"));
     /// ```
+    #[allow(dead_code)]
     fn new(value: T, meta: Span<'a>) -> Self {
         Token {
             value,
@@ -143,7 +266,7 @@ impl<'a> Span<'a> {
     /// assert_eq!(1, meta.column);
     /// assert_eq!(&b"abc"[..], meta.as_slice());
     /// ```
-    fn new(input: Input<'a>) -> Self {
+    pub fn new(input: Input<'a>) -> Self {
         Span {
             offset: 0,
             line: 1,
@@ -167,7 +290,8 @@ impl<'a> Span<'a> {
     /// assert_eq!(3, meta.column);
     /// assert_eq!(&b"abc"[..], meta.as_slice());
     /// ```
-    fn new_at(input: Input<'a>, offset: usize, line: u32, column: u32) -> Self {
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub(crate) fn new_at(input: Input<'a>, offset: usize, line: u32, column: u32) -> Self {
         Span {
             offset,
             line,
@@ -189,6 +313,7 @@ impl<'a> Span<'a> {
     /// 
     /// assert_eq!(Span::new(b""), meta);
     /// ```
+    #[allow(dead_code)]
     fn blank() -> Self {
         Self::new("")
     }
@@ -243,6 +368,11 @@ impl InputTake for Span<'_> {
     }
     
     /// Split the `Span` in half at `count`
+    /// 
+    /// `count` and `offset` are measured in bytes,
+    /// but the column is measured in Unicode scalar
+    /// values so that multi-byte characters only
+    /// advance it by one.
     fn take_split(&self, count: usize) -> (Self, Self) {
         let slice_a = &self.slice[..count];
         let slice_b = &self.slice[count..];
@@ -250,11 +380,12 @@ impl InputTake for Span<'_> {
         let lines = bytecount::count(slice_a.as_bytes(), b'\n') as u32;
         let col_b =
             if lines == 0 {
-                self.column + count as u32
+                self.column + slice_a.chars().count() as u32
             } else {
                 match memchr::memrchr(b'\n', slice_a.as_bytes()) {
                     Some(last_newline_position) => {
-                        (count - last_newline_position) as u32
+                        let last_line = &slice_a[last_newline_position + 1..];
+                        last_line.chars().count() as u32 + 1
                     },
     
                     None => {
@@ -327,6 +458,18 @@ impl<'a> InputIter for Span<'a> {
     }
 }
 
+/// Allows slicing a `Span` up to an index
+/// 
+/// Necessary to use `nom`'s `cut` combinator
+impl Slice<RangeTo<usize>> for Span<'_> {
+    /// Take a slice from the beginning of the `Span`
+    /// to the end of `range` (the same as `take`)
+    #[inline]
+    fn slice(&self, range: RangeTo<usize>) -> Self {
+        self.take(range.end)
+    }
+}
+
 /// Dummy trait allowing for default implementation
 /// of `InputTakeAtPosition`
 /// 
@@ -352,4 +495,20 @@ mod tests {
         
         assert_eq!(expected, span.take_split(3));
     }
+    
+    #[test]
+    fn input_take_split_multibyte() {
+        let span = Span::new("café!");
+        let expected = (Span::new_at("!", 5, 1, 5), Span::new("café"));
+        
+        assert_eq!(expected, span.take_split(5));
+    }
+    
+    #[test]
+    fn input_take_split_multibyte_multiline() {
+        let span = Span::new("a\nçé!");
+        let expected = (Span::new_at("!", 6, 2, 3), Span::new("a\nçé"));
+        
+        assert_eq!(expected, span.take_split(6));
+    }
 }
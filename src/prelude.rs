@@ -0,0 +1,26 @@
+//! Re-exports of the types most embedders need
+//! 
+//! # Examples
+//! 
+//! ```
+//! use crayne_lang::prelude::*;
+//! 
+//! let chunk = Chunk::new()
+//!     .add_constant(Value::Int(41))
+//!     .write(1, 1) // OP_CONSTANT
+//!     .write(0, 1)
+//!     .write(2, 1) // OP_INC
+//!     .write(0, 1); // OP_RETURN
+//! 
+//! assert_eq!(OpCode::Inc, OpCode::from(chunk.byte_at(2)));
+//! 
+//! println!("{}", disassemble_chunk(&chunk, "example"));
+//! assert_eq!(Ok(()), VM::new(chunk).run());
+//! ```
+
+pub use crate::vm::{VM, VMError, VMResult, TraceEvent, TraceHook};
+pub use crate::vm::chunk::{Chunk, FrozenChunk, OpCode};
+pub use crate::vm::value::{Value, Native, NativeFn};
+pub use crate::vm::heap::{Heap, Obj, ObjHandle};
+pub use crate::vm::clock::{Clock, SystemClock};
+pub use crate::tools::disassembler::disassemble_chunk;
\ No newline at end of file
@@ -0,0 +1,154 @@
+//! Track which opcodes a set of VM runs executed, to
+//! find opcodes that tests never reach
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+use crate::vm::VM;
+use crate::vm::chunk::OpCode;
+
+/// The opcodes executed by every VM it has been
+/// attached to
+/// 
+/// Clones share the same set, so one
+/// `ExecutedOpcodes` can be attached to many VMs
+/// and read once they have all run.
+#[derive(Debug, Default, Clone)]
+pub struct ExecutedOpcodes(Rc<RefCell<HashSet<OpCode>>>);
+
+impl ExecutedOpcodes {
+    /// Create an empty set of executed opcodes
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Record every opcode the VM executes, replacing
+    /// its trace hook
+    pub fn attach(&self, vm: &mut VM) {
+        let executed = Rc::clone(&self.0);
+        vm.set_trace_hook(Box::new(move |event| {
+            executed.borrow_mut().insert(event.opcode);
+        }));
+    }
+    
+    /// Return whether the opcode has been executed
+    pub fn contains(&self, opcode: OpCode) -> bool {
+        self.0.borrow().contains(&opcode)
+    }
+    
+    /// Return the valid opcodes that haven't been
+    /// executed, in byte order
+    pub fn unexecuted(&self) -> Vec<OpCode> {
+        (0..=u8::MAX)
+            .map(OpCode::from)
+            .filter(|opcode| !matches!(opcode, OpCode::Invalid(_)))
+            .filter(|&opcode| !self.contains(opcode))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{self, Cursor};
+    use crate::vm::chunk::Chunk;
+    use crate::vm::value::Value;
+    
+    /// A chunk that runs every opcode not already run
+    /// by `Chunk::loop_countdown`
+    fn remaining_opcodes() -> Chunk {
+        let code: &[(OpCode, &[u8])] = &[
+            (OpCode::SmallInt, &[5]),
+            (OpCode::Inc, &[]),
+            (OpCode::DefineGlobal, &[0]),
+            (OpCode::GetGlobal, &[0]),
+            (OpCode::SmallInt, &[1]),
+            (OpCode::Add, &[]),
+            (OpCode::SmallInt, &[1]),
+            (OpCode::Swap, &[]),
+            (OpCode::Subtract, &[]),
+            (OpCode::SmallInt, &[3]),
+            (OpCode::BitAnd, &[]),
+            (OpCode::SmallInt, &[1]),
+            (OpCode::BitOr, &[]),
+            (OpCode::SmallInt, &[6]),
+            (OpCode::BitXor, &[]),
+            (OpCode::SmallInt, &[2]),
+            (OpCode::ShiftLeft, &[]),
+            (OpCode::SmallInt, &[1]),
+            (OpCode::ShiftRight, &[]),
+            (OpCode::BitNot, &[]),
+            (OpCode::Pop, &[]),
+            (OpCode::Constant, &[1]),
+            (OpCode::Unpack, &[2]),
+            (OpCode::MakeRange, &[0]),
+            (OpCode::TypeOf, &[]),
+            (OpCode::Len, &[]),
+            (OpCode::Constant, &[2]),
+            (OpCode::Swap, &[]),
+            (OpCode::Pop, &[]),
+            (OpCode::ReadLine, &[]),
+            (OpCode::Concat, &[]),
+            (OpCode::SmallInt, &[0]),
+            (OpCode::GetIndex, &[]),
+            (OpCode::Print, &[]),
+            (OpCode::GetGlobal, &[5]),
+            (OpCode::SmallInt, &[15]),
+            (OpCode::Call, &[1]),
+            (OpCode::Constant, &[3]),
+            (OpCode::Format, &[]),
+            (OpCode::PrintNoNewline, &[]),
+            (OpCode::SmallInt, &[1]),
+            (OpCode::Constant, &[4]),
+            (OpCode::Is, &[]),
+            (OpCode::DupN, &[1]),
+            (OpCode::PrintStack, &[]),
+            (OpCode::Pop, &[]),
+            (OpCode::Pop, &[]),
+            (OpCode::SmallInt, &[6]),
+            (OpCode::SmallInt, &[3]),
+            (OpCode::Multiply, &[]),
+            (OpCode::SmallInt, &[2]),
+            (OpCode::Divide, &[]),
+            (OpCode::ToFloat, &[]),
+            (OpCode::Pop, &[]),
+            (OpCode::SmallInt, &[2]),
+            (OpCode::SmallInt, &[1]),
+            (OpCode::MakeArray, &[2]),
+            (OpCode::Sort, &[]),
+            (OpCode::Pop, &[]),
+            (OpCode::SmallInt, &[1]),
+            (OpCode::Negate, &[]),
+            (OpCode::Pop, &[]),
+            (OpCode::Return, &[])
+        ];
+        let chunk = Chunk::new()
+            .add_constant(Value::from("x"))
+            .add_constant(Value::Tuple(vec![Value::Int(2), Value::Int(3)]))
+            .add_constant(Value::from("ab"))
+            .add_constant(Value::from(">5"))
+            .add_constant(Value::from("int"))
+            .add_constant(Value::from("hex"));
+        code.iter().fold(chunk, |chunk, (opcode, operands)| {
+            operands.iter().fold(chunk.write(opcode.to_byte(), 1), |chunk, &byte| chunk.write(byte, 1))
+        })
+    }
+    
+    #[test]
+    fn every_opcode_is_executed() {
+        let executed = ExecutedOpcodes::new();
+        for chunk in [Chunk::loop_countdown(3), remaining_opcodes()] {
+            let mut vm = VM::new(chunk);
+            vm.set_input(Box::new(Cursor::new("in\n")));
+            vm.set_output(Box::new(io::sink()));
+            executed.attach(&mut vm);
+            
+            assert_eq!(Ok(()), vm.run());
+        }
+        
+        // `unexecuted` checks every byte that decodes to
+        // an opcode, so a new opcode fails this until a
+        // chunk above runs it
+        assert_eq!(Vec::<OpCode>::new(), executed.unexecuted());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,109 @@
+//! Compare the instructions of two chunks, e.g. before
+//! and after an optimization pass
+
+use crate::vm::chunk::Chunk;
+use crate::tools::disassembler::{decode_instruction, DisassembleOptions};
+
+/// Produce a line-by-line diff of the instructions
+/// in two chunks
+/// 
+/// Instructions only in `a` are prefixed with `-`,
+/// instructions only in `b` with `+`, and shared
+/// instructions with a space. Offsets and source
+/// lines are left out so that instructions that
+/// merely moved don't show up as changes.
+pub fn diff_chunks(a: &Chunk, b: &Chunk) -> String {
+    let a = instructions(a);
+    let b = instructions(b);
+    let common = common_suffix_lengths(&a, &b);
+    
+    let mut diff = String::from("--- a\n+++ b\n");
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            diff.push_str(&format!(" {}\n", a[i]));
+            i += 1;
+            j += 1;
+        } else if j == b.len() ||
+            (i < a.len() && common[i + 1][j] >= common[i][j + 1]) {
+            diff.push_str(&format!("-{}\n", a[i]));
+            i += 1;
+        } else {
+            diff.push_str(&format!("+{}\n", b[j]));
+            j += 1;
+        }
+    }
+    
+    diff
+}
+
+/// Decode every instruction in a chunk
+fn instructions(chunk: &Chunk) -> Vec<String> {
+    let mut instructions = vec![];
+    let mut offset = 0;
+    while offset < chunk.size() {
+        let (instruction, next_offset) = decode_instruction(chunk, offset, DisassembleOptions::default());
+        instructions.push(instruction.trim_end().to_string());
+        offset = next_offset;
+    }
+    
+    instructions
+}
+
+/// Build a table where `table[i][j]` is the length of
+/// the longest common subsequence of `a[i..]` and
+/// `b[j..]`
+fn common_suffix_lengths(a: &[String], b: &[String]) -> Vec<Vec<usize>> {
+    let mut table = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            table[i][j] = if a[i] == b[j] {
+                table[i + 1][j + 1] + 1
+            } else {
+                table[i + 1][j].max(table[i][j + 1])
+            };
+        }
+    }
+    
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::value::Value;
+    
+    #[test]
+    fn diff_removed_instructions() {
+        let unoptimized = Chunk::new()
+            .add_constant(Value::Int(1))
+            .write(1, 1)
+            .write(0, 1)
+            .write(2, 1)
+            .write(3, 1)
+            .write(0, 2);
+        let optimized = Chunk::new()
+            .add_constant(Value::Int(1))
+            .write(1, 1)
+            .write(0, 1)
+            .write(0, 2);
+        let expected = concat!(
+            "--- a\n",
+            "+++ b\n",
+            " OP_CONSTANT         0 'Int(1)'\n",
+            "-OP_INC\n",
+            "-OP_DEC\n",
+            " OP_RETURN\n"
+        );
+        
+        assert_eq!(expected, diff_chunks(&unoptimized, &optimized));
+    }
+    
+    #[test]
+    fn diff_added_instruction() {
+        let before = Chunk::new().write(0, 1);
+        let after = Chunk::new().write(2, 1).write(0, 1);
+        
+        assert_eq!("--- a\n+++ b\n+OP_INC\n OP_RETURN\n", diff_chunks(&before, &after));
+    }
+}
\ No newline at end of file
@@ -1,9 +1,38 @@
 use crate::vm::chunk::{OpCode, Chunk};
 
+/// Options controlling how a chunk is disassembled
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DisassembleOptions {
+    /// Stop decoding at the first invalid opcode and
+    /// dump the rest of the chunk as raw hex bytes
+    /// 
+    /// The length of an unknown instruction can't be
+    /// known, so anything decoded after it may be
+    /// misaligned.
+    pub hex_fallback: bool,
+    
+    /// Show constants in their display form, e.g.
+    /// `'32'`, instead of their debug form, e.g.
+    /// `'Int(32)'`, which tells apart values of
+    /// different types that display the same
+    pub values: bool,
+    
+    /// Show the raw bytes of each instruction before
+    /// its mnemonic
+    pub raw_bytes: bool
+}
+
 /// Disassemble a chunk into a human-readable
 /// format
 pub fn disassemble_chunk(chunk: &Chunk, name: &str) -> String {
-    format!("{}\n{}", chunk_header(name), chunk_body(chunk, 0))
+    disassemble_chunk_with(chunk, name, DisassembleOptions::default())
+}
+
+/// Disassemble a chunk into a human-readable
+/// format using the given options
+pub fn disassemble_chunk_with(chunk: &Chunk, name: &str, options: DisassembleOptions)
+    -> String {
+    format!("{}\n{}", chunk_header(name), chunk_body(chunk, 0, options))
 }
 
 /// Create a chunk header
@@ -12,13 +41,118 @@ fn chunk_header(name: &str) -> String {
 }
 
 /// Recursively create the body of a chunk
-fn chunk_body(chunk: &Chunk, offset: usize) -> String {
+fn chunk_body(chunk: &Chunk, offset: usize, options: DisassembleOptions) -> String {
     if offset >= chunk.size() {
         String::new()
+    } else if let (true, OpCode::Invalid(code)) =
+        (options.hex_fallback, OpCode::from(chunk.byte_at(offset))) {
+        format!(
+            "-- unknown opcode {} at {:04}, raw bytes follow --\n{}",
+            code,
+            offset,
+            raw_bytes(chunk, offset)
+        )
     } else {
-        let (result, next_offset) = disassemble_instruction(chunk, offset);
-        format!("{}{}", result, chunk_body(chunk, next_offset))
+        let (result, next_offset) = disassemble_instruction_with(chunk, offset, options);
+        format!("{}{}", result, chunk_body(chunk, next_offset, options))
+    }
+}
+
+/// Dump the raw bytes of a chunk's code in a
+/// classic hexdump format, 16 bytes per row
+/// 
+/// Each row shows the offset of its first byte, the
+/// bytes in hex, and the bytes as ASCII, with `.`
+/// standing in for unprintable bytes, e.g.
+/// 
+/// ```text
+/// 0000: 00 01 00                                         |...|
+/// ```
+pub fn hex_dump(chunk: &Chunk) -> String {
+    raw_bytes(chunk, 0)
+}
+
+/// Dump the bytes of a chunk from `offset` onward
+/// in the format of `hex_dump`
+fn raw_bytes(chunk: &Chunk, offset: usize) -> String {
+    (offset..chunk.size())
+        .step_by(16)
+        .map(|row| {
+            let bytes: Vec<u8> = (row..chunk.size().min(row + 16))
+                .map(|i| chunk.byte_at(i))
+                .collect();
+            let hex = bytes.iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let ascii: String = bytes.iter()
+                .map(|&byte| if byte == b' ' || byte.is_ascii_graphic() {
+                    byte as char
+                } else {
+                    '.'
+                })
+                .collect();
+            format!("{:04x}: {:<47}  |{}|\n", row, hex, ascii)
+        })
+        .collect()
+}
+
+/// Disassemble a chunk into a JSON array with one
+/// object per instruction, for tools that would
+/// otherwise have to parse the text format, e.g.
+/// 
+/// ```text
+/// [{"offset":0,"line":1,"op":"OP_CONSTANT","operands":[0],"constant_value":"32"}]
+/// ```
+/// 
+/// Operands are the raw operand bytes, and
+/// `constant_value` is only present on
+/// instructions that refer to a constant.
+pub fn disassemble_json(chunk: &Chunk) -> String {
+    let instructions: Vec<String> = chunk.instructions()
+        .map(|(offset, opcode)| {
+            let operands: Vec<u8> = (offset + 1..offset + 1 + opcode.operand_len())
+                .filter(|&i| i < chunk.size())
+                .map(|i| chunk.byte_at(i))
+                .collect();
+            let mut object = format!(
+                "{{\"offset\":{},\"line\":{},\"op\":{},\"operands\":[{}]",
+                offset,
+                chunk.get_line(offset),
+                json_string(opcode.mnemonic()),
+                operands.iter().map(u8::to_string).collect::<Vec<_>>().join(",")
+            );
+            let refers_to_constant = matches!(
+                opcode,
+                OpCode::Constant | OpCode::GetGlobal | OpCode::DefineGlobal | OpCode::SetGlobal
+            );
+            if let (true, Some(&index)) = (refers_to_constant, operands.first()) {
+                let value = chunk.const_val(index).to_string();
+                object.push_str(&format!(",\"constant_value\":{}", json_string(&value)));
+            }
+            object.push('}');
+            object
+        })
+        .collect();
+    format!("[{}]", instructions.join(","))
+}
+
+/// Quote and escape a string for JSON
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c)
+        }
     }
+    quoted.push('"');
+    quoted
 }
 
 /// Disassemble an instruction into a
@@ -27,15 +161,22 @@ fn chunk_body(chunk: &Chunk, offset: usize) -> String {
 /// the instruction
 pub fn disassemble_instruction(chunk: &Chunk, offset: usize)
     -> (String, usize) {
-    let (instruction, new_offset) =
-        match OpCode::from(chunk.byte_at(offset)) {
-            OpCode::Return => simple_instruction("OP_RETURN", offset),
-            OpCode::Constant => constant_instruction("OP_CONSTANT", chunk, offset),
-            OpCode::Invalid(code) => (
-                format!("Unknown opcode: {}\n", code),
-                offset + 1
-            )
-        };
+    disassemble_instruction_with(chunk, offset, DisassembleOptions::default())
+}
+
+/// Disassemble an instruction like
+/// `disassemble_instruction`, using the given
+/// options
+pub fn disassemble_instruction_with(chunk: &Chunk, offset: usize, options: DisassembleOptions)
+    -> (String, usize) {
+    let (mut instruction, new_offset) = decode_instruction(chunk, offset, options);
+    if options.raw_bytes {
+        let bytes = (offset..new_offset)
+            .map(|i| format!("{:02x}", chunk.byte_at(i)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        instruction = format!("{:<width$} {}", bytes, instruction, width = raw_bytes_width());
+    }
     let line = if offset > 0 && chunk.get_line(offset) == chunk.get_line(offset) {
         "   | ".to_string()
     } else {
@@ -49,15 +190,249 @@ pub fn disassemble_instruction(chunk: &Chunk, offset: usize)
     
 }
 
+/// The width of the raw bytes column, which fits the
+/// longest instruction
+fn raw_bytes_width() -> usize {
+    let longest = (0..=u8::MAX)
+        .map(|byte| 1 + OpCode::from(byte).operand_len())
+        .max()
+        .unwrap_or(1);
+    longest * 3 - 1
+}
+
+/// Decode the instruction at `offset` into its
+/// mnemonic and operands, without the offset and
+/// line columns, and return the text and the offset
+/// of the end of the instruction
+/// 
+/// An instruction whose operands run past the end of
+/// the chunk is marked `<truncated>` and ends the
+/// chunk, rather than showing operands read from
+/// beyond it.
+pub(crate) fn decode_instruction(chunk: &Chunk, offset: usize, options: DisassembleOptions)
+    -> (String, usize) {
+    let opcode = OpCode::from(chunk.byte_at(offset));
+    let name = opcode.mnemonic();
+    if offset + 1 + opcode.operand_len() > chunk.size() {
+        return (format!("{:-16} <truncated>\n", name), chunk.size());
+    }
+    match opcode {
+        OpCode::Constant |
+        OpCode::GetGlobal |
+        OpCode::DefineGlobal |
+        OpCode::SetGlobal => constant_instruction(name, chunk, offset, options),
+        OpCode::MakeRange |
+        OpCode::Unpack |
+        OpCode::Call |
+        OpCode::DupN |
+        OpCode::MakeArray => byte_instruction(name, chunk, offset),
+        OpCode::Jump |
+        OpCode::JumpIfFalse |
+        OpCode::Loop => jump_instruction(name, chunk, offset),
+        OpCode::SmallInt => small_int_instruction(name, chunk, offset),
+        OpCode::Invalid(code) => (
+            format!("Unknown opcode: {} (what follows may be misaligned)\n", code),
+            offset + 1
+        ),
+        _ => simple_instruction(name, offset)
+    }
+}
+
 /// Create the text for a simple instruction
 fn simple_instruction(text: &str, offset: usize) -> (String, usize) {
     (format!("{}\n", text), offset + 1)
 }
 
+/// Create the text for an instruction with a
+/// single byte operand
+fn byte_instruction(text: &str, chunk: &Chunk, offset: usize) -> (String, usize) {
+    (format!("{:-16} {:4}\n", text, chunk.byte_at(offset + 1)), offset + 2)
+}
+
+/// Create the text for an instruction whose operand
+/// is a signed byte
+fn small_int_instruction(text: &str, chunk: &Chunk, offset: usize) -> (String, usize) {
+    (format!("{:-16} {:4}\n", text, chunk.byte_at(offset + 1) as i8), offset + 2)
+}
+
+/// Create the text for a jump, showing the offset
+/// it jumps to
+fn jump_instruction(text: &str, chunk: &Chunk, offset: usize) -> (String, usize) {
+    let target = chunk.jump_target(offset).unwrap_or_default();
+    (format!("{:-16} {:4} -> {}\n", text, offset, target), offset + 3)
+}
+
 /// Create a text for a constant
-fn constant_instruction(text: &str, chunk: &Chunk, offset: usize) -> (String, usize) {
+fn constant_instruction(text: &str, chunk: &Chunk, offset: usize, options: DisassembleOptions)
+    -> (String, usize) {
     let constant = chunk.byte_at(offset + 1);
-    (format!("{:-16} {:4} '{}'\n", text, constant, chunk.const_val(constant)), offset + 2)
+    let value = if options.values {
+        chunk.const_val(constant).to_string()
+    } else {
+        format!("{:?}", chunk.const_val(constant))
+    };
+    (format!("{:-16} {:4} '{}'\n", text, constant, value), offset + 2)
 }
 
-// Add tests here when a full VM has been implemented
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::value::Value;
+    
+    #[test]
+    fn invalid_opcode_marks_misalignment() {
+        let chunk = Chunk::new().write(2, 1).write(42, 1).write(0, 1);
+        let expected = concat!(
+            "== test ==\n",
+            "0000    1 OP_INC\n",
+            "0001    | Unknown opcode: 42 (what follows may be misaligned)\n",
+            "0002    | OP_RETURN\n"
+        );
+        
+        assert_eq!(expected, disassemble_chunk(&chunk, "test"));
+    }
+    
+    #[test]
+    fn invalid_opcode_hex_fallback() {
+        let chunk = Chunk::new().write(2, 1).write(42, 1).write(3, 1).write(0, 1);
+        let options = DisassembleOptions { hex_fallback: true, ..Default::default() };
+        let expected = concat!(
+            "== test ==\n",
+            "0000    1 OP_INC\n",
+            "-- unknown opcode 42 at 0001, raw bytes follow --\n",
+            "0001: 2a 03 00                                         |*..|\n"
+        );
+        
+        assert_eq!(expected, disassemble_chunk_with(&chunk, "test", options));
+    }
+    
+    #[test]
+    fn hex_dump_test_chunk() {
+        let dump = hex_dump(&Chunk::test());
+        
+        assert!(dump.starts_with("0000: 00 01 00 "));
+        assert_eq!(
+            "0000: 00 01 00                                         |...|\n",
+            dump
+        );
+    }
+    
+    #[test]
+    fn hex_dump_rows() {
+        let mut chunk = Chunk::new();
+        for byte in b"Hello, hex dump!\n" {
+            chunk.push_byte(*byte, 1);
+        }
+        let expected = concat!(
+            "0000: 48 65 6c 6c 6f 2c 20 68 65 78 20 64 75 6d 70 21  |Hello, hex dump!|\n",
+            "0010: 0a                                               |.|\n"
+        );
+        
+        assert_eq!(expected, hex_dump(&chunk));
+    }
+    
+    /// A chunk with an int and a string constant that
+    /// display the same
+    fn ambiguous_constants() -> Chunk {
+        Chunk::new()
+            .add_constant(Value::Int(1))
+            .add_constant(Value::from("1"))
+            .write(1, 1)
+            .write(0, 1)
+            .write(1, 1)
+            .write(1, 1)
+    }
+    
+    #[test]
+    fn constants_in_debug_form() {
+        let expected = concat!(
+            "== test ==\n",
+            "0000    1 OP_CONSTANT         0 'Int(1)'\n",
+            "0002    | OP_CONSTANT         1 'Str(\"1\")'\n"
+        );
+        
+        assert_eq!(expected, disassemble_chunk(&ambiguous_constants(), "test"));
+    }
+    
+    #[test]
+    fn constants_in_display_form() {
+        let options = DisassembleOptions { values: true, ..Default::default() };
+        let expected = concat!(
+            "== test ==\n",
+            "0000    1 OP_CONSTANT         0 '1'\n",
+            "0002    | OP_CONSTANT         1 '1'\n"
+        );
+        
+        assert_eq!(expected, disassemble_chunk_with(&ambiguous_constants(), "test", options));
+    }
+    
+    #[test]
+    fn small_int_operand_is_signed() {
+        let chunk = Chunk::new().write(32, 1).write(42, 1).write(32, 1).write(0xfb, 1);
+        let expected = concat!(
+            "== test ==\n",
+            "0000    1 OP_SMALL_INT       42\n",
+            "0002    | OP_SMALL_INT       -5\n"
+        );
+        
+        assert_eq!(expected, disassemble_chunk(&chunk, "test"));
+    }
+    
+    #[test]
+    fn raw_bytes_before_mnemonic() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Int(32))
+            .write(1, 1)
+            .write(0, 1)
+            .write(0, 1);
+        let options = DisassembleOptions { raw_bytes: true, values: true, ..Default::default() };
+        let expected = concat!(
+            "== test ==\n",
+            "0000    1 01 00    OP_CONSTANT         0 '32'\n",
+            "0002    | 00       OP_RETURN\n"
+        );
+        
+        assert_eq!(expected, disassemble_chunk_with(&chunk, "test", options));
+    }
+    
+    #[test]
+    fn json_output() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Str("say \"hi\"\n".to_string()))
+            .write(1, 1).write(0, 1)
+            .write(2, 2)
+            .write(0, 2);
+        let expected = concat!(
+            "[",
+            r#"{"offset":0,"line":1,"op":"OP_CONSTANT","operands":[0],"#,
+            r#""constant_value":"say \"hi\"\n"},"#,
+            r#"{"offset":2,"line":2,"op":"OP_INC","operands":[]},"#,
+            r#"{"offset":3,"line":2,"op":"OP_RETURN","operands":[]}"#,
+            "]"
+        );
+        
+        assert_eq!(expected, disassemble_json(&chunk));
+    }
+    
+    #[test]
+    fn truncated_operand() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Int(1))
+            .write(2, 1)
+            .write(1, 1);
+        let expected = concat!(
+            "== test ==\n",
+            "0000    1 OP_INC\n",
+            "0001    | OP_CONSTANT      <truncated>\n"
+        );
+        
+        assert_eq!(expected, disassemble_chunk(&chunk, "test"));
+    }
+    
+    #[test]
+    fn dup_n_count() {
+        let chunk = Chunk::new().write(35, 1).write(2, 1);
+        
+        assert_eq!("0000    1 OP_DUP_N            2\n", disassemble_instruction(&chunk, 0).0);
+    }
+}
\ No newline at end of file
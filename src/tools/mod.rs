@@ -1 +1,4 @@
-pub mod disassembler;
\ No newline at end of file
+pub mod disassembler;
+pub mod diff;
+pub mod repl;
+pub mod coverage;
\ No newline at end of file
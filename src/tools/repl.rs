@@ -0,0 +1,167 @@
+//! Input handling for an interactive prompt, gathering
+//! lines until they form a complete entry
+
+use crate::parser::{parse_decl_partial, parse_expr_partial, is_incomplete};
+use crate::parser::ast::{Decl, Expr};
+use nom::Err;
+
+/// The prompt shown for a new entry
+pub const PROMPT: &str = "> ";
+
+/// The prompt shown while an entry is continued
+/// over several lines
+pub const CONTINUATION_PROMPT: &str = "... ";
+
+/// The outcome of feeding a line to a `LineBuffer`
+#[derive(Debug, PartialEq)]
+pub enum Entry<'a> {
+    /// The buffered lines parsed as a whole
+    /// expression
+    Complete(Expr<'a>),
+    
+    /// The buffered lines parsed as a whole
+    /// declaration, e.g. a function
+    Declaration(Decl<'a>),
+    
+    /// The buffered lines end too early, so more
+    /// lines are needed
+    Pending,
+    
+    /// The buffered lines contain a syntax error
+    Error(String)
+}
+
+/// Collects lines of input until they parse
+/// 
+/// An entry is a declaration or an expression. A
+/// line that leaves the entry unfinished, e.g.
+/// `(1 +` or `fn f() {`, is kept and joined with the lines after
+/// it. Once the entry parses or hits a syntax error,
+/// the buffer is cleared for the next one.
+#[derive(Debug, Default)]
+pub struct LineBuffer {
+    buffer: String,
+    
+    /// Whether the buffer holds a finished entry, which
+    /// is cleared when the next line is pushed, since a
+    /// complete entry borrows from the buffer
+    finished: bool
+}
+
+impl LineBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// The prompt to show before reading the next
+    /// line
+    pub fn prompt(&self) -> &'static str {
+        if self.buffer.is_empty() || self.finished {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        }
+    }
+    
+    /// Add a line to the current entry and try to
+    /// parse it
+    pub fn push_line(&mut self, line: &str) -> Entry<'_> {
+        if self.finished {
+            self.buffer.clear();
+            self.finished = false;
+        }
+        if self.buffer.is_empty() && line.trim().is_empty() {
+            return Entry::Pending;
+        }
+        
+        self.buffer.push_str(line);
+        self.buffer.push('\n');
+        
+        let parsed = match parse_decl_partial(&self.buffer) {
+            Err(error @ Err::Error(_)) if !is_incomplete(&error) => {
+                parse_expr_partial(&self.buffer).map(|(rest, expr)| (rest, Entry::Complete(expr)))
+            },
+            parsed => parsed.map(|(rest, decl)| (rest, Entry::Declaration(decl)))
+        };
+        if matches!(&parsed, Err(error) if is_incomplete(error)) {
+            return Entry::Pending;
+        }
+        self.finished = true;
+        
+        match parsed {
+            Ok((rest, entry)) => {
+                let rest_trimmed = rest.as_slice().trim_start();
+                if rest_trimmed.is_empty() {
+                    entry
+                } else {
+                    let skipped = rest.as_slice().len() - rest_trimmed.len();
+                    Entry::Error(format!(
+                        "unexpected input at line {}, column {}",
+                        rest.line,
+                        rest.column as usize + skipped
+                    ))
+                }
+            },
+            Err(Err::Error((rest, _))) | Err(Err::Failure((rest, _))) => Entry::Error(format!(
+                "syntax error at line {}, column {}",
+                rest.line,
+                rest.column
+            )),
+            Err(Err::Incomplete(_)) => unreachable!()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::ast::BinaryOp;
+    
+    #[test]
+    fn multi_line_entry() {
+        let mut lines = LineBuffer::new();
+        
+        assert_eq!(PROMPT, lines.prompt());
+        assert_eq!(Entry::Pending, lines.push_line("(1 +"));
+        assert_eq!(CONTINUATION_PROMPT, lines.prompt());
+        
+        match lines.push_line("  2) * 3") {
+            Entry::Complete(Expr::Binary { op, .. }) => assert_eq!(BinaryOp::Multiply, op),
+            entry => panic!("expected a complete entry, found {:?}", entry)
+        }
+        assert_eq!(PROMPT, lines.prompt());
+    }
+    
+    #[test]
+    fn multi_line_declaration() {
+        let mut lines = LineBuffer::new();
+        
+        assert_eq!(Entry::Pending, lines.push_line("/// Do nothing."));
+        assert_eq!(Entry::Pending, lines.push_line("fn f() {"));
+        assert_eq!(CONTINUATION_PROMPT, lines.prompt());
+        
+        match lines.push_line("}") {
+            Entry::Declaration(Decl::Function { doc, name, params, body }) => {
+                assert_eq!(Some("Do nothing.".to_string()), doc);
+                assert_eq!("f", name.as_slice());
+                assert!(params.is_empty());
+                assert_eq!(None, body);
+            },
+            entry => panic!("expected a declaration, found {:?}", entry)
+        }
+        assert_eq!(PROMPT, lines.prompt());
+        assert!(matches!(lines.push_line("f()"), Entry::Complete(Expr::Call { .. })));
+    }
+    
+    #[test]
+    fn syntax_error_clears_buffer() {
+        let mut lines = LineBuffer::new();
+        
+        assert_eq!(Entry::Pending, lines.push_line("1 *"));
+        assert_eq!(
+            Entry::Error(String::from("syntax error at line 2, column 1")),
+            lines.push_line(")")
+        );
+        assert_eq!(PROMPT, lines.prompt());
+    }
+}
\ No newline at end of file
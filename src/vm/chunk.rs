@@ -1,13 +1,105 @@
+use std::ops::Deref;
+use std::sync::Arc;
 use crate::vm::value::Value;
 use crate::vm::value::ConstantPool;
+use crate::vm::VMError;
 
 /// Represents the possible one-byte operation
 /// codes (opcodes) that describe the instruction
 /// that follows
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
 pub enum OpCode {
     Return,
     Constant,
+    
+    /// Add one to the number on top of the stack,
+    /// like `OpCode::Add` with `1`, updating an int in
+    /// place
+    Inc,
+    
+    /// Subtract one like `OpCode::Inc`
+    Dec,
+    MakeRange,
+    Unpack,
+    ReadLine,
+    Len,
+    GetIndex,
+    Add,
+    Subtract,
+    Swap,
+    GetGlobal,
+    Call,
+    
+    /// Jump forward by its 16-bit operand, counted
+    /// from the end of the operand
+    Jump,
+    PrintStack,
+    Concat,
+    
+    /// Jump forward like `OpCode::Jump` if the value
+    /// on top of the stack is `false`, leaving the
+    /// value on the stack
+    JumpIfFalse,
+    Pop,
+    DefineGlobal,
+    SetGlobal,
+    
+    /// Jump backward by its 16-bit operand, counted
+    /// from the end of the operand
+    Loop,
+    TypeOf,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    
+    /// Shift right, keeping the sign of the
+    /// integer being shifted
+    ShiftRight,
+    BitNot,
+    
+    /// Compare two values, looking through the
+    /// elements of tuples and arrays
+    Equal,
+    Print,
+    
+    /// Print like `OpCode::Print`, without a newline
+    /// after the value
+    PrintNoNewline,
+    
+    /// Push the integer held in its operand, which is
+    /// a signed byte (-128 to 127)
+    SmallInt,
+    
+    /// Format the value below the top of the stack
+    /// with the format spec string on top
+    Format,
+    
+    /// Push whether the value below the top of the
+    /// stack has the type named by the string on top
+    Is,
+    
+    /// Push copies of the top N values of the stack,
+    /// in the same order, where N is its operand
+    DupN,
+    Multiply,
+    Divide,
+    
+    /// Convert the int on top of the stack to a float,
+    /// leaving a float as it is
+    ToFloat,
+    
+    /// Pop the top N values, where N is its operand,
+    /// into a new array on the heap, keeping their
+    /// order
+    MakeArray,
+    
+    /// Sort the array on top of the stack in place,
+    /// leaving it on the stack
+    Sort,
+    
+    /// Negate the int or float on top of the stack
+    Negate,
     Invalid(u8)
 }
 
@@ -16,43 +108,276 @@ impl From<u8> for OpCode {
         match byte {
             0 => OpCode::Return,
             1 => OpCode::Constant,
+            2 => OpCode::Inc,
+            3 => OpCode::Dec,
+            4 => OpCode::MakeRange,
+            5 => OpCode::Unpack,
+            6 => OpCode::ReadLine,
+            7 => OpCode::Len,
+            8 => OpCode::GetIndex,
+            9 => OpCode::Add,
+            10 => OpCode::Subtract,
+            11 => OpCode::Swap,
+            12 => OpCode::GetGlobal,
+            13 => OpCode::Call,
+            14 => OpCode::Jump,
+            15 => OpCode::PrintStack,
+            16 => OpCode::Concat,
+            17 => OpCode::JumpIfFalse,
+            18 => OpCode::Pop,
+            19 => OpCode::DefineGlobal,
+            20 => OpCode::SetGlobal,
+            21 => OpCode::Loop,
+            22 => OpCode::TypeOf,
+            23 => OpCode::BitAnd,
+            24 => OpCode::BitOr,
+            25 => OpCode::BitXor,
+            26 => OpCode::ShiftLeft,
+            27 => OpCode::ShiftRight,
+            28 => OpCode::BitNot,
+            29 => OpCode::Equal,
+            30 => OpCode::Print,
+            31 => OpCode::PrintNoNewline,
+            32 => OpCode::SmallInt,
+            33 => OpCode::Format,
+            34 => OpCode::Is,
+            35 => OpCode::DupN,
+            36 => OpCode::Multiply,
+            37 => OpCode::Divide,
+            38 => OpCode::ToFloat,
+            39 => OpCode::MakeArray,
+            40 => OpCode::Sort,
+            41 => OpCode::Negate,
             invalid => OpCode::Invalid(invalid)
         }
     }
 }
 
+impl OpCode {
+    /// Return the byte the opcode is encoded as, the
+    /// inverse of `OpCode::from`
+    pub fn to_byte(&self) -> u8 {
+        match self {
+            OpCode::Return => 0,
+            OpCode::Constant => 1,
+            OpCode::Inc => 2,
+            OpCode::Dec => 3,
+            OpCode::MakeRange => 4,
+            OpCode::Unpack => 5,
+            OpCode::ReadLine => 6,
+            OpCode::Len => 7,
+            OpCode::GetIndex => 8,
+            OpCode::Add => 9,
+            OpCode::Subtract => 10,
+            OpCode::Swap => 11,
+            OpCode::GetGlobal => 12,
+            OpCode::Call => 13,
+            OpCode::Jump => 14,
+            OpCode::PrintStack => 15,
+            OpCode::Concat => 16,
+            OpCode::JumpIfFalse => 17,
+            OpCode::Pop => 18,
+            OpCode::DefineGlobal => 19,
+            OpCode::SetGlobal => 20,
+            OpCode::Loop => 21,
+            OpCode::TypeOf => 22,
+            OpCode::BitAnd => 23,
+            OpCode::BitOr => 24,
+            OpCode::BitXor => 25,
+            OpCode::ShiftLeft => 26,
+            OpCode::ShiftRight => 27,
+            OpCode::BitNot => 28,
+            OpCode::Equal => 29,
+            OpCode::Print => 30,
+            OpCode::PrintNoNewline => 31,
+            OpCode::SmallInt => 32,
+            OpCode::Format => 33,
+            OpCode::Is => 34,
+            OpCode::DupN => 35,
+            OpCode::Multiply => 36,
+            OpCode::Divide => 37,
+            OpCode::ToFloat => 38,
+            OpCode::MakeArray => 39,
+            OpCode::Sort => 40,
+            OpCode::Negate => 41,
+            OpCode::Invalid(byte) => *byte
+        }
+    }
+    
+    /// Return the name the opcode is shown with in
+    /// disassembly, e.g. `"OP_CONSTANT"`
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            OpCode::Return => "OP_RETURN",
+            OpCode::Constant => "OP_CONSTANT",
+            OpCode::Inc => "OP_INC",
+            OpCode::Dec => "OP_DEC",
+            OpCode::MakeRange => "OP_MAKE_RANGE",
+            OpCode::Unpack => "OP_UNPACK",
+            OpCode::ReadLine => "OP_READ_LINE",
+            OpCode::Len => "OP_LEN",
+            OpCode::GetIndex => "OP_GET_INDEX",
+            OpCode::Add => "OP_ADD",
+            OpCode::Subtract => "OP_SUBTRACT",
+            OpCode::Swap => "OP_SWAP",
+            OpCode::GetGlobal => "OP_GET_GLOBAL",
+            OpCode::Call => "OP_CALL",
+            OpCode::Jump => "OP_JUMP",
+            OpCode::PrintStack => "OP_PRINT_STACK",
+            OpCode::Concat => "OP_CONCAT",
+            OpCode::JumpIfFalse => "OP_JUMP_IF_FALSE",
+            OpCode::Pop => "OP_POP",
+            OpCode::DefineGlobal => "OP_DEFINE_GLOBAL",
+            OpCode::SetGlobal => "OP_SET_GLOBAL",
+            OpCode::Loop => "OP_LOOP",
+            OpCode::TypeOf => "OP_TYPE_OF",
+            OpCode::BitAnd => "OP_BIT_AND",
+            OpCode::BitOr => "OP_BIT_OR",
+            OpCode::BitXor => "OP_BIT_XOR",
+            OpCode::ShiftLeft => "OP_SHIFT_LEFT",
+            OpCode::ShiftRight => "OP_SHIFT_RIGHT",
+            OpCode::BitNot => "OP_BIT_NOT",
+            OpCode::Equal => "OP_EQUAL",
+            OpCode::Print => "OP_PRINT",
+            OpCode::PrintNoNewline => "OP_PRINT_NO_NEWLINE",
+            OpCode::SmallInt => "OP_SMALL_INT",
+            OpCode::Format => "OP_FORMAT",
+            OpCode::Is => "OP_IS",
+            OpCode::DupN => "OP_DUP_N",
+            OpCode::Multiply => "OP_MULTIPLY",
+            OpCode::Divide => "OP_DIVIDE",
+            OpCode::ToFloat => "OP_TO_FLOAT",
+            OpCode::MakeArray => "OP_MAKE_ARRAY",
+            OpCode::Sort => "OP_SORT",
+            OpCode::Negate => "OP_NEGATE",
+            OpCode::Invalid(_) => "OP_INVALID"
+        }
+    }
+    
+    /// Return the number of operand bytes that follow
+    /// the opcode
+    /// 
+    /// Invalid opcodes are treated as having no
+    /// operands, since their length can't be known.
+    pub fn operand_len(&self) -> usize {
+        match self {
+            OpCode::Constant |
+            OpCode::MakeRange |
+            OpCode::Unpack |
+            OpCode::GetGlobal |
+            OpCode::DefineGlobal |
+            OpCode::SetGlobal |
+            OpCode::Call |
+            OpCode::SmallInt |
+            OpCode::DupN |
+            OpCode::MakeArray => 1,
+            OpCode::Jump |
+            OpCode::JumpIfFalse |
+            OpCode::Loop => 2,
+            _ => 0
+        }
+    }
+    
+    /// Return whether the opcode is only meant for
+    /// debugging, so that production code can be
+    /// checked for it and rejected
+    pub fn is_debug_only(&self) -> bool {
+        matches!(self, OpCode::PrintStack)
+    }
+}
+
 /// A series of bytecode instructions
-#[derive(PartialEq, Debug)]
+/// 
+/// Every jump operand is relative to the end of the
+/// operand, so a chunk's code can be moved into a
+/// larger chunk without rewriting its jumps.
+#[derive(PartialEq, Debug, Default, Clone)]
 pub struct Chunk {
     code: Vec<u8>,
     constants: ConstantPool,
-    lines: Vec<u32>
+    lines: Vec<u32>,
+    columns: Vec<u32>
 }
 
 impl Chunk {
     /// Create a new chunk
-    fn new() -> Self {
+    pub fn new() -> Self {
         Chunk {
             code: vec![],
             constants: ConstantPool::new(),
-            lines: vec![]
+            lines: vec![],
+            columns: vec![]
         }
     }
     
     /// Add a byte to the chunk
-    fn write(mut self, byte: u8, line: u32) -> Self {
-        self.code.push(byte);
-        self.lines.push(line);
+    /// 
+    /// The column the byte came from is recorded
+    /// as `0`, meaning unknown.
+    pub fn write(self, byte: u8, line: u32) -> Self {
+        self.write_at(byte, line, 0)
+    }
+    
+    /// Add a byte to the chunk, recording both the
+    /// line and column it came from
+    pub fn write_at(mut self, byte: u8, line: u32, column: u32) -> Self {
+        self.push_byte_at(byte, line, column);
         self
     }
     
     /// Add a constant to the chunk
-    fn add_constant(self, value: Value) -> Self {
-        let constants = self.constants.write(value);
-        Chunk {
-            constants,
-            ..self
-        }
+    pub fn add_constant(mut self, value: Value) -> Self {
+        self.push_constant(value);
+        self
+    }
+    
+    /// Add a byte to the chunk in place
+    /// 
+    /// This is the `&mut self` equivalent of
+    /// `Chunk::write`.
+    pub fn push_byte(&mut self, byte: u8, line: u32) {
+        self.push_byte_at(byte, line, 0);
+    }
+    
+    /// Add a byte to the chunk in place, recording
+    /// both the line and column it came from
+    /// 
+    /// This is the `&mut self` equivalent of
+    /// `Chunk::write_at`.
+    pub fn push_byte_at(&mut self, byte: u8, line: u32, column: u32) {
+        self.code.push(byte);
+        self.lines.push(line);
+        self.columns.push(column);
+    }
+    
+    /// Add a constant to the chunk in place and
+    /// return its index in the constant pool
+    /// 
+    /// This is the `&mut self` equivalent of
+    /// `Chunk::add_constant`. It panics if the pool is
+    /// at its limit, unlike `Chunk::try_push_constant`.
+    pub fn push_constant(&mut self, value: Value) -> usize {
+        self.constants.push(value)
+    }
+    
+    /// Add a constant to the chunk in place and
+    /// return its index in the constant pool, or a
+    /// compile error if the pool is at its limit
+    pub fn try_push_constant(&mut self, value: Value) -> Result<usize, VMError> {
+        self.constants.try_push(value)
+    }
+    
+    /// Set the most constants that the chunk's
+    /// constant pool will hold
+    pub fn set_constant_limit(&mut self, limit: usize) {
+        self.constants.set_limit(limit);
+    }
+    
+    /// Add a constant to the chunk in place, reusing
+    /// an equal constant if the pool already has one,
+    /// and return its index in the constant pool
+    pub fn push_constant_unique(&mut self, value: Value) -> usize {
+        self.constants.push_unique(value)
     }
     
     /// Return the byte at a specific offset
@@ -62,7 +387,14 @@ impl Chunk {
     /// may cause unexpected behavior and will
     /// probably be changed later.
     pub fn byte_at(&self, offset: usize) -> u8 {
-        *self.code.get(offset).unwrap_or(&u8::max_value())
+        *self.code.get(offset).unwrap_or(&u8::MAX)
+    }
+    
+    /// Return the big-endian 16-bit value made of the
+    /// bytes at `offset` and `offset + 1`, as used by
+    /// jump operands
+    pub fn short_at(&self, offset: usize) -> u16 {
+        u16::from_be_bytes([self.byte_at(offset), self.byte_at(offset + 1)])
     }
     
     /// Return the constant denoted by the index
@@ -82,6 +414,145 @@ impl Chunk {
         self.const_val(self.byte_at(offset))
     }
     
+    /// Return the number of constants in the chunk's
+    /// constant pool
+    pub fn constant_count(&self) -> usize {
+        self.constants.len()
+    }
+    
+    /// Overwrite the byte at `offset`
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if `offset` is outside the chunk.
+    pub fn patch_byte(&mut self, offset: usize, byte: u8) {
+        self.code[offset] = byte;
+    }
+    
+    /// Fill in the operand of the jump instruction at
+    /// `jump_offset` so that it jumps to the current
+    /// end of the chunk
+    /// 
+    /// The jump distance is counted from the end of
+    /// the operand and must fit in 16 bits. It is an
+    /// error if there is no `OP_JUMP` or
+    /// `OP_JUMP_IF_FALSE` with its operand at
+    /// `jump_offset`.
+    pub fn patch_jump(&mut self, jump_offset: usize) -> Result<(), VMError> {
+        if !matches!(OpCode::from(self.byte_at(jump_offset)), OpCode::Jump | OpCode::JumpIfFalse) {
+            return Err(VMError::CompileError(format!(
+                "no jump instruction at offset {}",
+                jump_offset
+            )));
+        }
+        let distance = jump_offset.checked_add(3)
+            .and_then(|operand_end| self.size().checked_sub(operand_end))
+            .ok_or_else(|| VMError::CompileError(format!(
+                "jump at offset {} is missing its operand",
+                jump_offset
+            )))?;
+        if distance > u16::MAX as usize {
+            return Err(VMError::CompileError("too much code to jump over".to_string()));
+        }
+        
+        let [high, low] = (distance as u16).to_be_bytes();
+        self.patch_byte(jump_offset + 1, high);
+        self.patch_byte(jump_offset + 2, low);
+        Ok(())
+    }
+    
+    /// Iterate over the chunk's instructions, yielding
+    /// the offset and opcode of each
+    pub fn instructions(&self) -> Instructions<'_> {
+        Instructions {
+            chunk: self,
+            offset: 0
+        }
+    }
+    
+    /// Return the byte offset of each instruction in
+    /// order, so that the index of an instruction can
+    /// be mapped to its offset and back
+    pub fn instruction_offsets(&self) -> Vec<usize> {
+        self.instructions().map(|(offset, _)| offset).collect()
+    }
+    
+    /// Return the offset that the jump instruction at
+    /// `offset` jumps to, or `None` if the instruction
+    /// isn't a jump
+    /// 
+    /// The target is signed, since a malformed
+    /// backward jump can point before the chunk.
+    pub fn jump_target(&self, offset: usize) -> Option<i64> {
+        let after_operand = offset as i64 + 3;
+        let distance = self.short_at(offset + 1) as i64;
+        match OpCode::from(self.byte_at(offset)) {
+            OpCode::Jump | OpCode::JumpIfFalse => Some(after_operand + distance),
+            OpCode::Loop => Some(after_operand - distance),
+            _ => None
+        }
+    }
+    
+    /// Return a copy of the chunk for placing `base`
+    /// bytes into a larger chunk
+    /// 
+    /// Since jumps are relative, the code doesn't need
+    /// to change. Instead, this checks that every jump
+    /// lands inside the chunk, so that none of them
+    /// depend on where the chunk is placed.
+    pub fn relocate(&self, base: usize) -> Result<Chunk, VMError> {
+        for (offset, _) in self.instructions() {
+            if let Some(target) = self.jump_target(offset) {
+                if target < 0 || target > self.size() as i64 {
+                    return Err(VMError::CompileError(format!(
+                        "jump at offset {} leaves the chunk",
+                        base + offset
+                    )));
+                }
+            }
+        }
+        
+        Ok(self.clone())
+    }
+    
+    /// Remove constants that no instruction refers to,
+    /// renumbering the ones that are left
+    /// 
+    /// Constants are kept in the order they were in,
+    /// and the operands of the instructions that refer
+    /// to them are rewritten to their new indices.
+    pub fn gc_constants(mut self) -> Chunk {
+        let operands: Vec<usize> = self.instructions()
+            .filter(|(_, opcode)| matches!(
+                opcode,
+                OpCode::Constant | OpCode::GetGlobal | OpCode::DefineGlobal | OpCode::SetGlobal
+            ))
+            .map(|(offset, _)| offset + 1)
+            .collect();
+        
+        let mut live = vec![false; self.constants.len()];
+        for &operand in &operands {
+            if let Some(slot) = live.get_mut(self.byte_at(operand) as usize) {
+                *slot = true;
+            }
+        }
+        
+        let mut constants = ConstantPool::new();
+        constants.set_limit(self.constants.limit());
+        let mut new_indices = vec![None; live.len()];
+        for (index, _) in live.iter().enumerate().filter(|(_, live)| **live) {
+            new_indices[index] = Some(constants.push(self.constants.get_const(index)) as u8);
+        }
+        
+        for operand in operands {
+            if let Some(Some(new_index)) = new_indices.get(self.byte_at(operand) as usize) {
+                self.patch_byte(operand, *new_index);
+            }
+        }
+        self.constants = constants;
+        self
+    }
+    
     /// Returns the line of the code that the
     /// byte refers to
     /// 
@@ -91,11 +562,65 @@ impl Chunk {
         *self.lines.get(index).unwrap_or(&0)
     }
     
+    /// Returns the column of the code that the
+    /// byte refers to
+    /// 
+    /// If the index is outside of the code, or the
+    /// column wasn't recorded, it will return `0`
+    pub fn get_column(&self, index: usize) -> u32 {
+        *self.columns.get(index).unwrap_or(&0)
+    }
+    
+    /// Map the offset of each instruction to the line
+    /// and column of the source it came from
+    pub fn source_map(&self) -> Vec<(usize, u32, u32)> {
+        self.instructions()
+            .map(|(offset, _)| (offset, self.get_line(offset), self.get_column(offset)))
+            .collect()
+    }
+    
     /// Return the size of the chunk
     pub fn size(&self) -> usize {
         self.code.len()
     }
     
+    /// Compare the code and constants of two chunks,
+    /// ignoring which lines the code came from
+    /// 
+    /// Unlike `==`, this is true for chunks compiled
+    /// from the same source laid out differently.
+    pub fn code_eq(&self, other: &Chunk) -> bool {
+        self.code == other.code && self.constants == other.constants
+    }
+    
+    /// Return a hash of the chunk's code, constants,
+    /// and lines that is the same for equal chunks in
+    /// every run of the program, e.g. for keying a
+    /// compilation cache
+    /// 
+    /// Natives are hashed by name, since their
+    /// addresses change between runs.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = ContentHasher::new();
+        hasher.write_usize(self.code.len());
+        hasher.write(&self.code);
+        hasher.write_usize(self.constants.len());
+        for index in 0..self.constants.len() {
+            hasher.write_value(&self.constants.get_const(index));
+        }
+        hasher.write_usize(self.lines.len());
+        for line in &self.lines {
+            hasher.write(&line.to_le_bytes());
+        }
+        hasher.finish()
+    }
+    
+    /// Make the chunk read-only so it can be shared,
+    /// e.g. by VMs on several threads
+    pub fn freeze(self) -> FrozenChunk {
+        FrozenChunk(Arc::new(self))
+    }
+    
     /// A test chunk for manually testing/running
     /// that can be modified as needed. Should
     /// not be used for production code.
@@ -103,11 +628,183 @@ impl Chunk {
         Chunk {
             code: vec![0, 1, 0],
             constants: ConstantPool::new().write(Value::Int(32)),
-            lines: vec![1, 1, 1]
+            lines: vec![1, 1, 1],
+            columns: vec![0, 0, 0]
         }
     }
 }
 
+/// A chunk that can no longer be changed, created by
+/// `Chunk::freeze`
+/// 
+/// Clones share the same chunk, and it can be sent
+/// to other threads, so a program can be compiled
+/// once and run by many VMs.
+#[derive(PartialEq, Debug, Clone)]
+pub struct FrozenChunk(Arc<Chunk>);
+
+impl Deref for FrozenChunk {
+    type Target = Chunk;
+    
+    fn deref(&self) -> &Chunk {
+        &self.0
+    }
+}
+
+impl From<Chunk> for FrozenChunk {
+    fn from(chunk: Chunk) -> Self {
+        chunk.freeze()
+    }
+}
+
+/// A 64-bit FNV-1a hasher, which unlike the
+/// standard library's hasher is guaranteed to give
+/// the same result on every platform and version
+struct ContentHasher(u64);
+
+impl ContentHasher {
+    fn new() -> Self {
+        ContentHasher(0xcbf2_9ce4_8422_2325)
+    }
+    
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    
+    fn write_usize(&mut self, n: usize) {
+        self.write(&(n as u64).to_le_bytes());
+    }
+    
+    /// Hash a value along with its type, so that
+    /// e.g. `Int(1)` and `Char('\u{1}')` differ
+    fn write_value(&mut self, value: &Value) {
+        self.write(value.type_name().as_bytes());
+        match value {
+            Value::Nil | Value::DoesNotExist => {},
+            Value::Bool(b) => self.write(&[*b as u8]),
+            Value::Int(i) => self.write(&i.to_le_bytes()),
+            Value::Float(x) => self.write(&x.to_bits().to_le_bytes()),
+            Value::Char(c) => self.write(&(*c as u32).to_le_bytes()),
+            Value::Str(string) => {
+                self.write_usize(string.len());
+                self.write(string.as_bytes());
+            },
+            Value::Bytes(bytes) => {
+                self.write_usize(bytes.len());
+                self.write(bytes);
+            },
+            Value::Range { start, end, inclusive } => {
+                self.write(&start.to_le_bytes());
+                self.write(&end.to_le_bytes());
+                self.write(&[*inclusive as u8]);
+            },
+            Value::Tuple(values) => {
+                self.write_usize(values.len());
+                for value in values {
+                    self.write_value(value);
+                }
+            },
+            Value::Native(native) => {
+                self.write_usize(native.name.len());
+                self.write(native.name.as_bytes());
+            },
+            Value::Obj(handle) => self.write_usize(handle.index())
+        }
+    }
+    
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// An iterator over the instructions of a chunk,
+/// created by `Chunk::instructions`
+/// 
+/// Invalid opcodes are treated as having no operands,
+/// so anything after one may be misaligned.
+pub struct Instructions<'a> {
+    chunk: &'a Chunk,
+    offset: usize
+}
+
+impl Iterator for Instructions<'_> {
+    type Item = (usize, OpCode);
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.chunk.size() {
+            return None;
+        }
+        let offset = self.offset;
+        let opcode = OpCode::from(self.chunk.byte_at(offset));
+        self.offset += 1 + opcode.operand_len();
+        Some((offset, opcode))
+    }
+}
+
+/// Chunks for tests that exercise different mixes
+/// of opcodes
+#[cfg(test)]
+impl Chunk {
+    /// A chunk that adds two integers and returns
+    /// the sum
+    pub(crate) fn arith_add(a: i64, b: i64) -> Self {
+        Chunk::new()
+            .add_constant(Value::Int(a))
+            .add_constant(Value::Int(b))
+            .write(1, 1)
+            .write(0, 1)
+            .write(1, 1)
+            .write(1, 1)
+            .write(9, 1)
+            .write(0, 1)
+    }
+    
+    /// A chunk that stores `n` in the global `n` and
+    /// decrements it until it reaches 0, leaving the
+    /// final value on the stack
+    /// 
+    /// `OP_DEC` is executed `n` times, as long as `n`
+    /// isn't negative.
+    pub(crate) fn loop_countdown(n: i64) -> Self {
+        Chunk::new()
+            .add_constant(Value::Int(n))
+            .add_constant(Value::from("n"))
+            .add_constant(Value::Int(0))
+            .write(1, 1)
+            .write(0, 1)
+            .write(19, 1)
+            .write(1, 1)
+            .write(12, 2)
+            .write(1, 2)
+            .write(1, 2)
+            .write(2, 2)
+            .write(29, 2)
+            .write(17, 2)
+            .write(0, 2)
+            .write(4, 2)
+            .write(18, 2)
+            .write(14, 2)
+            .write(0, 2)
+            .write(10, 2)
+            .write(18, 3)
+            .write(12, 3)
+            .write(1, 3)
+            .write(3, 3)
+            .write(20, 3)
+            .write(1, 3)
+            .write(18, 3)
+            .write(21, 3)
+            .write(0, 3)
+            .write(22, 3)
+            .write(12, 4)
+            .write(1, 4)
+            .write(0, 4)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -117,7 +814,8 @@ mod tests {
         let expected = Chunk {
             code: vec![1],
             constants: ConstantPool::new(),
-            lines: vec![1]
+            lines: vec![1],
+            columns: vec![0]
         };
         let actual = Chunk::new().write(1, 1);
         
@@ -129,10 +827,251 @@ mod tests {
         let expected = Chunk {
             code: vec![],
             constants: ConstantPool::new().write(Value::Int(1)),
-            lines: vec![]
+            lines: vec![],
+            columns: vec![]
         };
         let actual = Chunk::new().add_constant(Value::Int(1));
         
         assert_eq!(expected, actual);
     }
+    
+    #[test]
+    fn build_chunk_in_place() {
+        let expected = Chunk::new()
+            .add_constant(Value::Int(1))
+            .add_constant(Value::Int(2))
+            .write(1, 1)
+            .write(1, 1)
+            .write_at(0, 2, 3);
+        let mut actual = Chunk::new();
+        assert_eq!(0, actual.push_constant(Value::Int(1)));
+        assert_eq!(1, actual.push_constant(Value::Int(2)));
+        actual.push_byte(1, 1);
+        actual.push_byte(1, 1);
+        actual.push_byte_at(0, 2, 3);
+        
+        assert_eq!(expected, actual);
+    }
+    
+    #[test]
+    fn patch_forward_jump() {
+        let mut chunk = Chunk::new();
+        chunk.push_byte(14, 1);
+        chunk.push_byte(0xff, 1);
+        chunk.push_byte(0xff, 1);
+        for _ in 0..300 {
+            chunk.push_byte(2, 2);
+        }
+        
+        assert_eq!(Ok(()), chunk.patch_jump(0));
+        assert_eq!(1, chunk.byte_at(1));
+        assert_eq!(44, chunk.byte_at(2));
+        assert_eq!(300, chunk.short_at(1));
+    }
+    
+    #[test]
+    fn patch_jump_too_far() {
+        let mut chunk = Chunk::new();
+        chunk.push_byte(14, 1);
+        chunk.push_byte(0xff, 1);
+        chunk.push_byte(0xff, 1);
+        for _ in 0..=u16::MAX as usize {
+            chunk.push_byte(2, 2);
+        }
+        
+        assert_eq!(
+            Err(VMError::CompileError("too much code to jump over".to_string())),
+            chunk.patch_jump(0)
+        );
+    }
+    
+    #[test]
+    fn patch_jump_errors() {
+        let mut chunk = Chunk::new().write(2, 1).write(17, 1).write(0, 1);
+        
+        assert_eq!(
+            Err(VMError::CompileError("no jump instruction at offset 0".to_string())),
+            chunk.patch_jump(0)
+        );
+        assert_eq!(
+            Err(VMError::CompileError("no jump instruction at offset 9".to_string())),
+            chunk.patch_jump(9)
+        );
+        assert_eq!(
+            Err(VMError::CompileError("jump at offset 1 is missing its operand".to_string())),
+            chunk.patch_jump(1)
+        );
+        
+        chunk.push_byte(0, 1);
+        chunk.push_byte(2, 1);
+        
+        assert_eq!(Ok(()), chunk.patch_jump(1));
+        assert_eq!(1, chunk.short_at(2));
+    }
+    
+    #[test]
+    fn map_instructions_to_source() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Int(41))
+            .write_at(1, 1, 9)
+            .write_at(0, 1, 9)
+            .write_at(2, 1, 7)
+            .write_at(0, 2, 1);
+        
+        assert_eq!(vec![(0, 1, 9), (2, 1, 7), (3, 2, 1)], chunk.source_map());
+    }
+    
+    #[test]
+    fn code_eq_ignores_lines() {
+        let a = Chunk::new().add_constant(Value::Int(1)).write(1, 1).write(0, 1);
+        let b = Chunk::new().add_constant(Value::Int(1)).write(1, 2).write(0, 3);
+        
+        assert_ne!(a, b);
+        assert!(a.code_eq(&b));
+    }
+    
+    #[test]
+    fn code_eq_compares_constants() {
+        let a = Chunk::new().add_constant(Value::Int(1)).write(1, 1).write(0, 1);
+        let b = Chunk::new().add_constant(Value::Int(2)).write(1, 1).write(0, 1);
+        
+        assert!(!a.code_eq(&b));
+    }
+    
+    /// Assemble a chunk that jumps forward over a
+    /// return, then loops back to it
+    fn forward_and_backward_jumps() -> Chunk {
+        Chunk::new()
+            .add_constant(Value::Int(1))
+            .write(14, 1)
+            .write(0, 1)
+            .write(3, 1)
+            .write(1, 2)
+            .write(0, 2)
+            .write(0, 2)
+            .write(21, 3)
+            .write(0, 3)
+            .write(6, 3)
+    }
+    
+    #[test]
+    fn jump_targets() {
+        let chunk = forward_and_backward_jumps();
+        
+        assert_eq!(Some(6), chunk.jump_target(0));
+        assert_eq!(Some(3), chunk.jump_target(6));
+        assert_eq!(None, chunk.jump_target(3));
+    }
+    
+    #[test]
+    fn relocate_preserves_behavior() {
+        let chunk = forward_and_backward_jumps();
+        let relocated = chunk.relocate(100).unwrap();
+        
+        assert!(relocated.code_eq(&chunk));
+        
+        let mut vm = crate::vm::VM::new(relocated);
+        assert_eq!(Ok(Value::Int(1)), vm.interpret_verified());
+    }
+    
+    #[test]
+    fn relocate_rejects_jump_out_of_chunk() {
+        let chunk = Chunk::new()
+            .write(0, 1)
+            .write(21, 1)
+            .write(0, 1)
+            .write(9, 1);
+        
+        assert_eq!(
+            Err(VMError::CompileError("jump at offset 11 leaves the chunk".to_string())),
+            chunk.relocate(10)
+        );
+    }
+    
+    #[test]
+    fn gc_unused_constants() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Int(1))
+            .add_constant(Value::from("unused"))
+            .add_constant(Value::from("x"))
+            .write(1, 1)
+            .write(0, 1)
+            .write(19, 1)
+            .write(2, 1)
+            .write(12, 2)
+            .write(2, 2)
+            .write(0, 2)
+            .gc_constants();
+        
+        assert_eq!(2, chunk.constant_count());
+        assert_eq!(Value::Int(1), chunk.read_const(1));
+        assert_eq!(Value::from("x"), chunk.read_const(3));
+        assert_eq!(Value::from("x"), chunk.read_const(5));
+        
+        let mut vm = crate::vm::VM::new(chunk);
+        assert_eq!(Ok(Value::Int(1)), vm.interpret_verified());
+    }
+    
+    #[test]
+    fn offsets_of_instructions() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Int(1))
+            .write(1, 1)
+            .write(0, 1)
+            .write(2, 1)
+            .write(14, 1)
+            .write(0, 1)
+            .write(0, 1)
+            .write(13, 1)
+            .write(0, 1)
+            .write(0, 1);
+        
+        assert_eq!(vec![0, 2, 3, 6, 8], chunk.instruction_offsets());
+        assert_eq!(
+            vec![OpCode::Constant, OpCode::Inc, OpCode::Jump, OpCode::Call, OpCode::Return],
+            chunk.instructions().map(|(_, opcode)| opcode).collect::<Vec<_>>()
+        );
+    }
+    
+    #[test]
+    fn opcode_encoding() {
+        let one_byte_operand = [1, 4, 5, 12, 13, 19, 20, 32, 35, 39];
+        let two_byte_operand = [14, 17, 21];
+        for byte in 0..=u8::MAX {
+            let opcode = OpCode::from(byte);
+            
+            assert_eq!(byte, opcode.to_byte());
+            let expected_len = if byte > 41 {
+                assert_eq!(OpCode::Invalid(byte), opcode);
+                0
+            } else if one_byte_operand.contains(&byte) {
+                1
+            } else if two_byte_operand.contains(&byte) {
+                2
+            } else {
+                0
+            };
+            assert_eq!(expected_len, opcode.operand_len(), "operand length of {:?}", opcode);
+        }
+    }
+    
+    #[test]
+    fn content_hash_is_structural() {
+        let build = || Chunk::new()
+            .add_constant(Value::Float(1.5))
+            .add_constant(Value::Tuple(vec![Value::from("a"), Value::Bytes(b"b"[..].into())]))
+            .write(1, 1)
+            .write(0, 1)
+            .write(1, 2)
+            .write(1, 2)
+            .write(0, 2);
+        
+        assert_eq!(build().content_hash(), build().content_hash());
+        assert_ne!(build().content_hash(), build().write(0, 3).content_hash());
+        assert_ne!(
+            build().content_hash(),
+            build().add_constant(Value::Int(0)).content_hash()
+        );
+        assert_ne!(Chunk::arith_add(1, 2).content_hash(), Chunk::arith_add(2, 1).content_hash());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,27 @@
+//! Sources of the current time for the `clock` native
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A source of the current time
+/// 
+/// Clocks are shared by natives, which may be used
+/// from several threads, so they must be `Send` and
+/// `Sync`.
+pub trait Clock: Send + Sync {
+    /// Return the current time in seconds
+    fn now(&self) -> f64;
+}
+
+/// The clock used by default, which reads the system
+/// time as seconds since the Unix epoch
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> f64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs_f64())
+            .unwrap_or(0.0)
+    }
+}
\ No newline at end of file
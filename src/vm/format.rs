@@ -0,0 +1,197 @@
+//! Formatting values with a small subset of Rust's
+//! format spec, for `OP_FORMAT`
+
+use crate::vm::value::Value;
+
+/// The largest width or precision a format spec can
+/// have, so that a spec can't ask for a huge string
+pub const MAX_FORMAT_WIDTH: usize = 1024;
+
+/// Where a value is placed when it is narrower than
+/// the width it is padded to
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Align {
+    Left,
+    Center,
+    Right
+}
+
+/// A parsed format spec, e.g. `{:*^8.2}`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct FormatSpec {
+    /// The character used for padding
+    pub fill: char,
+    
+    /// The alignment, or `None` for the default of
+    /// right for numbers and left for everything else
+    pub align: Option<Align>,
+    
+    /// The minimum width in characters
+    pub width: Option<usize>,
+    
+    /// The number of digits after the decimal point
+    /// for floats, or the most characters to show for
+    /// anything else except integers
+    pub precision: Option<usize>
+}
+
+impl Default for FormatSpec {
+    fn default() -> Self {
+        FormatSpec {
+            fill: ' ',
+            align: None,
+            width: None,
+            precision: None
+        }
+    }
+}
+
+/// Parse a format spec of the form
+/// `{:[[fill]align][width][.precision]}`
+/// 
+/// The surrounding `{:` and `}` may be left out, and
+/// `align` is one of `<`, `^`, or `>`. A width or
+/// precision over `MAX_FORMAT_WIDTH` is an error.
+pub fn parse_format_spec(spec: &str) -> Result<FormatSpec, String> {
+    let inner = spec.strip_prefix("{:")
+        .and_then(|inner| inner.strip_suffix('}'))
+        .unwrap_or(spec);
+    let mut format_spec = FormatSpec::default();
+    let mut chars: Vec<char> = inner.chars().collect();
+    
+    let to_align = |c: char| match c {
+        '<' => Some(Align::Left),
+        '^' => Some(Align::Center),
+        '>' => Some(Align::Right),
+        _ => None
+    };
+    if let Some(align) = chars.get(1).cloned().and_then(to_align) {
+        format_spec.fill = chars[0];
+        format_spec.align = Some(align);
+        chars.drain(..2);
+    } else if let Some(align) = chars.first().cloned().and_then(to_align) {
+        format_spec.align = Some(align);
+        chars.remove(0);
+    }
+    
+    let rest: String = chars.into_iter().collect();
+    let (width, precision) = match rest.find('.') {
+        Some(dot) => (&rest[..dot], Some(&rest[dot + 1..])),
+        None => (&rest[..], None)
+    };
+    let parse_number = |digits: &str| match digits.parse::<usize>() {
+        Ok(number) if number <= MAX_FORMAT_WIDTH => Ok(number),
+        Ok(_) => Err(format!(
+            "format spec '{}' is too large (the limit is {})",
+            spec,
+            MAX_FORMAT_WIDTH
+        )),
+        Err(_) => Err(format!("invalid format spec '{}'", spec))
+    };
+    
+    if !width.is_empty() {
+        format_spec.width = Some(parse_number(width)?);
+    }
+    if let Some(precision) = precision {
+        format_spec.precision = Some(parse_number(precision)?);
+    }
+    Ok(format_spec)
+}
+
+impl FormatSpec {
+    /// Format a value according to the spec
+    /// 
+    /// The width and precision are clamped to
+    /// `MAX_FORMAT_WIDTH`.
+    pub fn apply(&self, value: &Value) -> String {
+        let precision = self.precision.map(|precision| precision.min(MAX_FORMAT_WIDTH));
+        let text = match (value, precision) {
+            (Value::Float(x), Some(precision)) => format!("{:.*}", precision, x),
+            (Value::Int(_), _) | (_, None) => value.to_string(),
+            (_, Some(precision)) => value.to_string().chars().take(precision).collect()
+        };
+        
+        let length = text.chars().count();
+        let padding = self.width.unwrap_or(0).min(MAX_FORMAT_WIDTH).saturating_sub(length);
+        let default_align = match value {
+            Value::Int(_) | Value::Float(_) => Align::Right,
+            _ => Align::Left
+        };
+        let (before, after) = match self.align.unwrap_or(default_align) {
+            Align::Left => (0, padding),
+            Align::Center => (padding / 2, padding - padding / 2),
+            Align::Right => (padding, 0)
+        };
+        
+        let fill = |count| std::iter::repeat_n(self.fill, count).collect::<String>();
+        format!("{}{}{}", fill(before), text, fill(after))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn parse_full_spec() {
+        let expected = FormatSpec {
+            fill: '*',
+            align: Some(Align::Center),
+            width: Some(8),
+            precision: Some(2)
+        };
+        
+        assert_eq!(Ok(expected), parse_format_spec("{:*^8.2}"));
+        assert_eq!(Ok(expected), parse_format_spec("*^8.2"));
+    }
+    
+    #[test]
+    fn parse_invalid_spec() {
+        assert_eq!(
+            Err("invalid format spec '{:>x}'".to_string()),
+            parse_format_spec("{:>x}")
+        );
+    }
+    
+    #[test]
+    fn right_align_int() {
+        assert_eq!("   42", parse_format_spec("{:>5}").unwrap().apply(&Value::Int(42)));
+    }
+    
+    #[test]
+    fn float_precision() {
+        assert_eq!("1.23", parse_format_spec("{:.2}").unwrap().apply(&Value::Float(1.23456)));
+    }
+    
+    #[test]
+    fn default_alignment() {
+        let spec = parse_format_spec("{:4}").unwrap();
+        
+        assert_eq!("  42", spec.apply(&Value::Int(42)));
+        assert_eq!("ab  ", spec.apply(&Value::from("ab")));
+    }
+    
+    #[test]
+    fn center_with_fill() {
+        assert_eq!("**ab***", parse_format_spec("{:*^7}").unwrap().apply(&Value::from("ab")));
+    }
+    
+    #[test]
+    fn width_limit() {
+        let widest = format!("{{:{}.{}}}", MAX_FORMAT_WIDTH, MAX_FORMAT_WIDTH);
+        let spec = parse_format_spec(&widest).unwrap();
+        
+        assert_eq!(MAX_FORMAT_WIDTH, spec.apply(&Value::Int(1)).len());
+        for spec in &["{:99999999999}", "{:.99999999999}", "{:1025}", "{:99999999999999999999999}"] {
+            assert!(parse_format_spec(spec).is_err(), "{} should be rejected", spec);
+        }
+        assert_eq!(
+            Err("format spec '{:1025}' is too large (the limit is 1024)".to_string()),
+            parse_format_spec("{:1025}")
+        );
+        
+        let huge = FormatSpec { width: Some(usize::MAX), ..FormatSpec::default() };
+        
+        assert_eq!(MAX_FORMAT_WIDTH, huge.apply(&Value::Int(1)).len());
+    }
+}
\ No newline at end of file
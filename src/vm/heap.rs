@@ -0,0 +1,383 @@
+//! Objects the VM allocates at runtime, which values
+//! refer to by handle
+
+use std::fmt;
+use crate::vm::value::Value;
+
+/// The number of objects a heap can hold before it
+/// asks to be collected, unless it's given a
+/// different threshold
+pub const DEFAULT_GC_THRESHOLD: usize = 1024;
+
+/// A reference to an object on a `Heap`, held by
+/// `Value::Obj`
+/// 
+/// A handle only means something to the heap that
+/// allocated it.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub struct ObjHandle(usize);
+
+impl ObjHandle {
+    /// Return the slot the object is stored in
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// An object allocated on a `Heap`
+/// 
+/// Strings stay in `Value::Str`, since they can't be
+/// changed after they are made and so can't take
+/// part in a cycle.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Obj {
+    Array(Vec<Value>)
+}
+
+impl Obj {
+    /// Return the name of the object's type, e.g.
+    /// `"array"`
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Obj::Array(_) => "array"
+        }
+    }
+}
+
+/// An arena of objects owned by a VM
+/// 
+/// Objects are stored in slots, and the slot of an
+/// object that is collected is reused by the next
+/// allocation. Every object still on the heap is
+/// dropped along with it, including objects that
+/// refer to each other.
+#[derive(Debug)]
+pub struct Heap {
+    slots: Vec<Option<Obj>>,
+    free: Vec<usize>,
+    threshold: usize,
+    next_collection: usize
+}
+
+impl Heap {
+    /// Create an empty heap
+    pub fn new() -> Self {
+        Heap {
+            slots: vec![],
+            free: vec![],
+            threshold: DEFAULT_GC_THRESHOLD,
+            next_collection: DEFAULT_GC_THRESHOLD
+        }
+    }
+    
+    /// Set how many objects the heap can hold before
+    /// `Heap::should_collect` first returns `true`
+    pub fn set_threshold(&mut self, threshold: usize) {
+        self.threshold = threshold;
+        self.next_collection = threshold;
+    }
+    
+    /// Move an object onto the heap and return its
+    /// handle
+    pub fn alloc(&mut self, obj: Obj) -> ObjHandle {
+        match self.free.pop() {
+            Some(index) => {
+                self.slots[index] = Some(obj);
+                ObjHandle(index)
+            },
+            None => {
+                self.slots.push(Some(obj));
+                ObjHandle(self.slots.len() - 1)
+            }
+        }
+    }
+    
+    /// Return the object a handle refers to, or
+    /// `None` if there is no such object
+    pub fn get(&self, handle: ObjHandle) -> Option<&Obj> {
+        self.slots.get(handle.0).and_then(Option::as_ref)
+    }
+    
+    /// Return the object a handle refers to mutably,
+    /// or `None` if there is no such object
+    pub fn get_mut(&mut self, handle: ObjHandle) -> Option<&mut Obj> {
+        self.slots.get_mut(handle.0).and_then(Option::as_mut)
+    }
+    
+    /// Return the number of objects on the heap
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+    
+    /// Return whether the heap has no objects
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    
+    /// Return whether the heap has grown enough since
+    /// the last collection to be collected again
+    pub fn should_collect(&self) -> bool {
+        self.len() >= self.next_collection
+    }
+    
+    /// Free every object that can't be reached from
+    /// `roots`, and return how many were freed
+    /// 
+    /// Objects are marked by following the values in
+    /// arrays and tuples, so a cycle of arrays that
+    /// nothing else refers to is freed. A handle to a
+    /// freed object may later refer to a new object in
+    /// the same slot. The next collection is due once
+    /// the heap is twice the size it is left at, or at
+    /// the threshold, whichever is larger.
+    pub fn collect(&mut self, roots: &[Value]) -> usize {
+        self.collect_from(roots)
+    }
+    
+    /// Collect like `Heap::collect`, with roots that
+    /// may come from several places
+    pub(crate) fn collect_from<'a, I>(&mut self, roots: I) -> usize
+    where I: IntoIterator<Item = &'a Value> {
+        let mut marked = vec![false; self.slots.len()];
+        let mut pending = vec![];
+        for root in roots {
+            trace(root, &mut pending);
+        }
+        while let Some(handle) = pending.pop() {
+            match marked.get_mut(handle.0) {
+                Some(mark) if !*mark => *mark = true,
+                _ => continue
+            }
+            if let Some(Obj::Array(values)) = self.get(handle) {
+                for value in values {
+                    trace(value, &mut pending);
+                }
+            }
+        }
+        
+        let mut freed = 0;
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            if slot.is_some() && !marked[index] {
+                *slot = None;
+                self.free.push(index);
+                freed += 1;
+            }
+        }
+        self.next_collection = (self.len() * 2).max(self.threshold);
+        freed
+    }
+    
+    /// Return the name of a value's type, looking up
+    /// the type of the object it refers to if it is a
+    /// `Value::Obj`
+    pub fn type_name(&self, value: &Value) -> &'static str {
+        match value {
+            Value::Obj(handle) => self.get(*handle)
+                .map_or_else(|| value.type_name(), Obj::type_name),
+            other => other.type_name()
+        }
+    }
+    
+    /// Return whether two values are equal, comparing
+    /// arrays and tuples element by element
+    /// 
+    /// A pair of arrays that is already being compared
+    /// is treated as equal when it is reached again, so
+    /// comparing self-referential arrays terminates.
+    pub fn equal(&self, a: &Value, b: &Value) -> bool {
+        self.equal_in(a, b, &mut vec![])
+    }
+    
+    fn equal_in(&self, a: &Value, b: &Value, seen: &mut Vec<(ObjHandle, ObjHandle)>) -> bool {
+        match (a, b) {
+            (Value::Obj(x), Value::Obj(y)) if x == y || seen.contains(&(*x, *y)) => true,
+            (Value::Obj(x), Value::Obj(y)) => match (self.get(*x), self.get(*y)) {
+                (Some(Obj::Array(xs)), Some(Obj::Array(ys))) => {
+                    seen.push((*x, *y));
+                    self.all_equal(xs, ys, seen)
+                },
+                _ => false
+            },
+            (Value::Tuple(xs), Value::Tuple(ys)) => self.all_equal(xs, ys, seen),
+            (a, b) => a == b
+        }
+    }
+    
+    fn all_equal(&self, xs: &[Value], ys: &[Value], seen: &mut Vec<(ObjHandle, ObjHandle)>)
+        -> bool {
+        xs.len() == ys.len() && xs.iter().zip(ys).all(|(x, y)| self.equal_in(x, y, seen))
+    }
+    
+    /// Display a value along with the objects it
+    /// refers to, e.g. `[1, "a"]` for an array
+    /// 
+    /// An array that contains itself is shown as
+    /// `[...]` where it appears inside itself.
+    pub fn show<'a>(&'a self, value: &'a Value) -> Shown<'a> {
+        Shown { heap: self, value }
+    }
+    
+    fn fmt_value(&self, value: &Value, nested: bool, open: &mut Vec<ObjHandle>,
+        f: &mut fmt::Formatter) -> fmt::Result {
+        match value {
+            Value::Obj(handle) if open.contains(handle) => write!(f, "[...]"),
+            Value::Obj(handle) => match self.get(*handle) {
+                Some(Obj::Array(values)) => {
+                    open.push(*handle);
+                    self.fmt_sequence(values, "[", "]", open, f)?;
+                    open.pop();
+                    Ok(())
+                },
+                None => write!(f, "{}", value)
+            },
+            Value::Tuple(values) => self.fmt_sequence(values, "(", ")", open, f),
+            other if nested => other.fmt_nested(f),
+            other => write!(f, "{}", other)
+        }
+    }
+    
+    fn fmt_sequence(&self, values: &[Value], start: &str, end: &str,
+        open: &mut Vec<ObjHandle>, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", start)?;
+        for (i, value) in values.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            self.fmt_value(value, true, open, f)?;
+        }
+        write!(f, "{}", end)
+    }
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Add the objects a value refers to directly, or
+/// through tuples, to the objects still to be marked
+fn trace(value: &Value, pending: &mut Vec<ObjHandle>) {
+    match value {
+        Value::Obj(handle) => pending.push(*handle),
+        Value::Tuple(values) => {
+            for value in values {
+                trace(value, pending);
+            }
+        },
+        _ => {}
+    }
+}
+
+/// A value displayed with the objects it refers to,
+/// created by `Heap::show`
+pub struct Shown<'a> {
+    heap: &'a Heap,
+    value: &'a Value
+}
+
+impl fmt::Display for Shown<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.heap.fmt_value(self.value, false, &mut vec![], f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    
+    #[test]
+    fn resolve_objects() {
+        let mut heap = Heap::new();
+        let a = heap.alloc(Obj::Array(vec![Value::Int(1), Value::from("a")]));
+        let b = heap.alloc(Obj::Array(vec![Value::Obj(a)]));
+        
+        assert_eq!(2, heap.len());
+        assert_eq!(
+            Some(&Obj::Array(vec![Value::Int(1), Value::from("a")])),
+            heap.get(a)
+        );
+        assert_eq!(Some(&Obj::Array(vec![Value::Obj(a)])), heap.get(b));
+        assert_eq!("array", heap.type_name(&Value::Obj(b)));
+        assert_eq!("[[1, \"a\"]]", heap.show(&Value::Obj(b)).to_string());
+    }
+    
+    #[test]
+    fn objects_dropped_with_heap() {
+        let bytes: Arc<[u8]> = Arc::from(&b"ab"[..]);
+        let mut heap = Heap::new();
+        let array = heap.alloc(Obj::Array(vec![Value::Bytes(Arc::clone(&bytes))]));
+        if let Some(Obj::Array(values)) = heap.get_mut(array) {
+            values.push(Value::Obj(array));
+        }
+        
+        assert_eq!(2, Arc::strong_count(&bytes));
+        drop(heap);
+        assert_eq!(1, Arc::strong_count(&bytes));
+    }
+    
+    #[test]
+    fn show_cycle() {
+        let mut heap = Heap::new();
+        let array = heap.alloc(Obj::Array(vec![Value::Int(1)]));
+        if let Some(Obj::Array(values)) = heap.get_mut(array) {
+            values.push(Value::Obj(array));
+        }
+        let tuple = Value::Tuple(vec![Value::Char('c'), Value::Obj(array)]);
+        
+        assert_eq!("('c', [1, [...]])", heap.show(&tuple).to_string());
+    }
+    
+    #[test]
+    fn collect_unreachable() {
+        let mut heap = Heap::new();
+        let garbage = heap.alloc(Obj::Array(vec![Value::Int(1)]));
+        let kept = heap.alloc(Obj::Array(vec![Value::Int(2)]));
+        let inner = heap.alloc(Obj::Array(vec![]));
+        let root = Value::Tuple(vec![Value::Obj(kept)]);
+        if let Some(Obj::Array(values)) = heap.get_mut(kept) {
+            values.push(Value::Obj(inner));
+        }
+        
+        assert_eq!(1, heap.collect(&[root]));
+        assert_eq!(2, heap.len());
+        assert_eq!(None, heap.get(garbage));
+        assert_eq!(Some(&Obj::Array(vec![])), heap.get(inner));
+        assert_eq!(garbage, heap.alloc(Obj::Array(vec![])));
+    }
+    
+    #[test]
+    fn collect_cycles() {
+        let mut heap = Heap::new();
+        let a = heap.alloc(Obj::Array(vec![]));
+        let b = heap.alloc(Obj::Array(vec![Value::Obj(a)]));
+        if let Some(Obj::Array(values)) = heap.get_mut(a) {
+            values.push(Value::Obj(b));
+        }
+        let rooted = heap.alloc(Obj::Array(vec![]));
+        if let Some(Obj::Array(values)) = heap.get_mut(rooted) {
+            values.push(Value::Obj(rooted));
+        }
+        
+        assert_eq!(2, heap.collect(&[Value::Obj(rooted)]));
+        assert_eq!(1, heap.len());
+        assert!(heap.get(rooted).is_some());
+        assert_eq!(0, heap.collect(&[Value::Obj(rooted)]));
+        assert_eq!(1, heap.collect(&[]));
+        assert!(heap.is_empty());
+    }
+    
+    #[test]
+    fn collection_threshold() {
+        let mut heap = Heap::new();
+        heap.set_threshold(2);
+        let kept = heap.alloc(Obj::Array(vec![]));
+        
+        assert!(!heap.should_collect());
+        heap.alloc(Obj::Array(vec![]));
+        assert!(heap.should_collect());
+        heap.collect(&[Value::Obj(kept)]);
+        assert!(!heap.should_collect());
+    }
+}
\ No newline at end of file
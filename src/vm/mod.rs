@@ -1,47 +1,2206 @@
 pub mod chunk;
 pub mod value;
+pub mod clock;
+pub mod verifier;
+pub mod format;
+pub mod heap;
 
-use chunk::{Chunk, OpCode};
+use std::fmt;
+use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::io::{self, BufRead, BufReader, Write};
+use chunk::{FrozenChunk, OpCode};
+use value::{Value, Native, Radix, ArithmeticOp};
+use clock::{Clock, SystemClock};
+use heap::{Heap, Obj, ObjHandle};
 use crate::debug;
 use crate::tools::disassembler::disassemble_instruction;
 
+/// The number of values the stack has room for
+/// before it needs to reallocate, unless the VM
+/// is created with `VM::with_capacity`
+const DEFAULT_STACK_CAPACITY: usize = 256;
+
+/// The state of the VM just before it executes
+/// an instruction, passed to the trace hook
+#[derive(Debug)]
+pub struct TraceEvent<'a> {
+    /// The offset of the instruction
+    pub ip: usize,
+    
+    /// The decoded instruction
+    pub opcode: OpCode,
+    
+    /// The values on the stack, from bottom to top
+    pub stack: &'a [Value]
+}
+
+/// A callback invoked before each instruction
+pub type TraceHook = Box<dyn FnMut(&TraceEvent)>;
+
 /// The virtual machine
-struct VM {
-    chunk: Chunk
+pub struct VM {
+    chunk: FrozenChunk,
+    ip: usize,
+    stack: Vec<Value>,
+    heap: Heap,
+    globals: Vec<Value>,
+    global_slots: HashMap<String, usize>,
+    global_cache: Vec<Option<usize>>,
+    program_globals: HashSet<String>,
+    trace_hook: Option<TraceHook>,
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    profile: bool,
+    opcode_counts: [u64; 256],
+    allow_redefinition: bool,
+    print_radix: Radix,
+    strict: bool
 }
 
 impl VM {
+    /// Create a VM that will run the given chunk,
+    /// which may be a `Chunk` or a `FrozenChunk`
+    /// shared with other VMs
+    pub fn new(chunk: impl Into<FrozenChunk>) -> Self {
+        Self::with_capacity(chunk, DEFAULT_STACK_CAPACITY)
+    }
+    
+    /// Create a VM that will run the given chunk,
+    /// with room for `stack_capacity` values on the
+    /// stack before it needs to reallocate
+    pub fn with_capacity(chunk: impl Into<FrozenChunk>, stack_capacity: usize) -> Self {
+        let mut vm = VM {
+            chunk: chunk.into(),
+            ip: 0,
+            stack: Vec::with_capacity(stack_capacity),
+            heap: Heap::new(),
+            globals: vec![],
+            global_slots: HashMap::new(),
+            global_cache: vec![],
+            program_globals: HashSet::new(),
+            trace_hook: None,
+            input: Box::new(BufReader::new(io::stdin())),
+            output: Box::new(io::stdout()),
+            profile: false,
+            opcode_counts: [0; 256],
+            allow_redefinition: false,
+            print_radix: Radix::Decimal,
+            strict: false
+        };
+        vm.set_clock(Arc::new(SystemClock));
+        vm.define_radix_native("hex", Radix::Hex);
+        vm.define_radix_native("bin", Radix::Binary);
+        vm
+    }
+    
+    /// Set the clock read by the `clock()` native,
+    /// which is the system clock by default
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.define_native("clock", 0, move |_| Ok(Value::Float(clock.now())));
+    }
+    
+    /// Define a native that shows an integer in the
+    /// given radix, e.g. `hex(255)` is `"0xff"`
+    fn define_radix_native(&mut self, name: &str, radix: Radix) {
+        self.define_native(name, 1, move |args| args[0].to_radix_string(radix)
+            .map(Value::Str)
+            .ok_or_else(|| format!("expected an int but got a {}", args[0].type_name())));
+    }
+    
+    /// Define a global function implemented in Rust
+    /// 
+    /// The function is given exactly `arity` arguments
+    /// when called. If it returns an error, the error
+    /// becomes a runtime error.
+    pub fn define_native<F>(&mut self, name: &str, arity: u8, function: F)
+    where F: Fn(&[Value]) -> Result<Value, String> + Send + Sync + 'static {
+        let native = Native {
+            name: name.to_string(),
+            arity,
+            function: Box::new(function)
+        };
+        self.define_global(name, Value::Native(Arc::new(native)));
+    }
+    
+    /// Define a global variable, or replace the value
+    /// of an existing one
+    /// 
+    /// A global keeps its storage slot when it is
+    /// redefined, so cached lookups stay valid.
+    pub fn define_global(&mut self, name: &str, value: Value) {
+        match self.global_slots.get(name) {
+            Some(&slot) => self.globals[slot] = value,
+            None => {
+                self.global_slots.insert(name.to_string(), self.globals.len());
+                self.globals.push(value);
+            }
+        }
+    }
+    
+    /// Set the source that `OP_READ_LINE` reads from,
+    /// which is standard input by default
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.input = input;
+    }
+    
+    /// Set the sink that `OP_PRINT` and
+    /// `OP_PRINT_STACK` write to, which is standard
+    /// output by default
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+    
+    /// Set the radix that `OP_PRINT` and
+    /// `OP_PRINT_NO_NEWLINE` show integers in, which
+    /// is decimal by default
+    pub fn set_print_radix(&mut self, radix: Radix) {
+        self.print_radix = radix;
+    }
+    
+    /// Set whether arithmetic on an int and a float
+    /// is an error rather than promoting the int, so
+    /// that ints have to be converted explicitly with
+    /// `OP_TO_FLOAT`
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+    
+    /// Set how many objects the heap can hold before
+    /// its garbage is first collected
+    pub fn set_gc_threshold(&mut self, threshold: usize) {
+        self.heap.set_threshold(threshold);
+    }
+    
+    /// Set whether `OP_DEFINE_GLOBAL` may redefine a
+    /// global that the program already defined
+    /// 
+    /// This is off by default, since defining a global
+    /// twice in a file is likely a mistake, but a REPL
+    /// should turn it on. Globals defined by the host,
+    /// such as natives, can always be redefined.
+    pub fn set_allow_redefinition(&mut self, allow: bool) {
+        self.allow_redefinition = allow;
+    }
+    
+    /// Turn counting how many times each opcode is
+    /// executed on or off
+    pub fn set_profiling(&mut self, profile: bool) {
+        self.profile = profile;
+    }
+    
+    /// Return how many times each opcode has been
+    /// executed while profiling was on
+    /// 
+    /// Opcodes that were never executed are left out.
+    pub fn opcode_counts(&self) -> HashMap<OpCode, u64> {
+        self.opcode_counts.iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(byte, count)| (OpCode::from(byte as u8), *count))
+            .collect()
+    }
+    
+    /// Register a callback to be invoked before each
+    /// instruction is executed, replacing any callback
+    /// that was already registered
+    pub fn set_trace_hook(&mut self, hook: TraceHook) {
+        self.trace_hook = Some(hook);
+    }
+    
+    /// Return the heap holding the objects the
+    /// program has allocated
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+    
+    /// Verify the chunk, and run it only if it passes
+    /// 
+    /// Returns the value left on top of the stack, or
+    /// `Value::Nil` if the stack is empty. A chunk
+    /// that fails verification is rejected with a
+    /// compile error before any of it runs.
+    pub fn interpret_verified(&mut self) -> Result<Value, VMError> {
+        verifier::verify(&self.chunk)?;
+        self.run()?;
+        Ok(self.stack.last().cloned().unwrap_or(Value::Nil))
+    }
+    
     /// Run the VM
-    fn run(&self) -> VMResult {
-        let mut ip = 0;
-        let mut result = Ok(());
-        
-        loop {
-            debug!(disassemble_instruction(&self.chunk, ip));
-            let instruction = self.chunk.byte_at(ip);
-            ip += 1;
-            match OpCode::from(instruction) {
-                OpCode::Return => break,
-                OpCode::Constant => {
-                    let constant = self.chunk.read_const(ip + 1);
-                    ip += 1;
-                    // TODO Get rid of this
-                    println!("{}", constant);
-                },
-                OpCode::Invalid(_) => {
-                    result = Err(VMError::CompileError);
-                    break;
+    pub fn run(&mut self) -> VMResult {
+        self.ip = 0;
+        while !self.step()? {}
+        Ok(())
+    }
+    
+    /// Run at most `max_steps` instructions, picking up
+    /// where the last call left off
+    /// 
+    /// This lets several VMs take turns on one thread.
+    /// A VM that yields can be resumed by calling this
+    /// again.
+    pub fn run_for(&mut self, max_steps: u64) -> RunState {
+        for _ in 0..max_steps {
+            match self.step() {
+                Ok(true) => return RunState::Halted,
+                Ok(false) => {},
+                Err(error) => return RunState::Errored(error)
+            }
+        }
+        RunState::Yielded
+    }
+    
+    /// Execute the instruction at the instruction
+    /// pointer, returning whether the VM halted
+    fn step(&mut self) -> Result<bool, VMError> {
+        let mut ip = self.ip;
+        debug!(disassemble_instruction(&self.chunk, ip));
+        let offset = ip;
+        let byte = self.chunk.byte_at(ip);
+        let opcode = OpCode::from(byte);
+        ip += 1;
+        if self.profile {
+            self.opcode_counts[byte as usize] += 1;
+        }
+        if let Some(hook) = self.trace_hook.as_mut() {
+            hook(&TraceEvent { ip: offset, opcode, stack: &self.stack });
+        }
+        match opcode {
+            OpCode::Return => {
+                self.ip = ip;
+                return Ok(true);
+            },
+            OpCode::Constant => {
+                let constant = self.chunk.read_const(ip);
+                ip += 1;
+                self.stack.push(constant);
+            },
+            OpCode::Inc | OpCode::Dec => {
+                let inc = opcode == OpCode::Inc;
+                match self.stack.last_mut() {
+                    Some(Value::Int(i)) => {
+                        *i = if inc { i.checked_add(1) } else { i.checked_sub(1) }
+                            .ok_or_else(overflow_error)?;
+                    },
+                    _ => {
+                        self.stack.push(Value::Int(1));
+                        self.arithmetic(if inc { ArithmeticOp::Add } else { ArithmeticOp::Subtract })?;
+                    }
+                }
+            },
+            OpCode::MakeRange => {
+                let inclusive = self.chunk.byte_at(ip) != 0;
+                ip += 1;
+                let end = self.pop_int()?;
+                let start = self.pop_int()?;
+                self.stack.push(Value::Range { start, end, inclusive });
+            },
+            OpCode::Unpack => {
+                let arity = self.chunk.byte_at(ip) as usize;
+                ip += 1;
+                match self.pop()? {
+                    Value::Tuple(values) if values.len() == arity => {
+                        self.stack.extend(values);
+                    },
+                    Value::Tuple(values) => {
+                        return Err(VMError::RuntimeError(format!(
+                            "expected a tuple of {} values, found {}",
+                            arity,
+                            values.len()
+                        )));
+                    },
+                    other => {
+                        return Err(VMError::RuntimeError(format!(
+                            "expected a tuple, found {}",
+                            other
+                        )));
+                    }
+                }
+            },
+            OpCode::ReadLine => {
+                let line = self.read_line()?;
+                self.stack.push(line);
+            },
+            OpCode::Len => {
+                let length = match self.pop()? {
+                    Value::Str(string) => string.chars().count(),
+                    Value::Bytes(bytes) => bytes.len(),
+                    Value::Tuple(values) => values.len(),
+                    Value::Obj(handle) => match resolve(&self.heap, handle)? {
+                        Obj::Array(values) => values.len()
+                    },
+                    other => {
+                        return Err(VMError::RuntimeError(format!(
+                            "{} has no length",
+                            other
+                        )));
+                    }
+                };
+                self.stack.push(Value::Int(length as i64));
+            },
+            OpCode::GetIndex => {
+                let index = self.pop_int()?;
+                let collection = self.pop()?;
+                let element = get_index(&self.heap, collection, index)?;
+                self.stack.push(element);
+            },
+            OpCode::Add => self.arithmetic(ArithmeticOp::Add)?,
+            OpCode::Subtract => self.arithmetic(ArithmeticOp::Subtract)?,
+            OpCode::Multiply => self.arithmetic(ArithmeticOp::Multiply)?,
+            OpCode::Divide => self.arithmetic(ArithmeticOp::Divide)?,
+            OpCode::Negate => {
+                let negated = match self.pop()? {
+                    Value::Int(i) => Value::Int(i.checked_neg().ok_or_else(overflow_error)?),
+                    Value::Float(x) => Value::Float(-x),
+                    other => {
+                        return Err(VMError::RuntimeError(format!("cannot negate {}", other)));
+                    }
+                };
+                self.stack.push(negated);
+            },
+            OpCode::ToFloat => {
+                let x = match self.pop()? {
+                    Value::Int(i) => i as f64,
+                    Value::Float(x) => x,
+                    other => {
+                        return Err(VMError::RuntimeError(format!(
+                            "cannot convert {} to a float",
+                            other
+                        )));
+                    }
+                };
+                self.stack.push(Value::Float(x));
+            },
+            OpCode::Swap => {
+                let top = self.stack.len();
+                if top < 2 {
+                    return Err(underflow_error());
                 }
+                self.stack.swap(top - 1, top - 2);
+            },
+            OpCode::DupN => {
+                let count = self.chunk.byte_at(ip) as usize;
+                ip += 1;
+                let top = self.stack.len();
+                if top < count {
+                    return Err(underflow_error());
+                }
+                self.stack.extend_from_within(top - count..);
+            },
+            OpCode::GetGlobal => {
+                let slot = self.global_slot(self.chunk.byte_at(ip))?;
+                ip += 1;
+                self.stack.push(self.globals[slot].clone());
+            },
+            OpCode::DefineGlobal => {
+                let name = String::try_from(self.chunk.read_const(ip))
+                    .map_err(VMError::RuntimeError)?;
+                ip += 1;
+                if !self.allow_redefinition && self.program_globals.contains(&name) {
+                    return Err(VMError::RuntimeError(format!(
+                        "global '{}' already defined",
+                        name
+                    )));
+                }
+                let value = self.pop()?;
+                self.define_global(&name, value);
+                self.program_globals.insert(name);
+            },
+            OpCode::SetGlobal => {
+                let slot = self.global_slot(self.chunk.byte_at(ip))?;
+                ip += 1;
+                let value = self.stack.last().cloned().ok_or_else(underflow_error)?;
+                self.globals[slot] = value;
+            },
+            OpCode::Call => {
+                let arg_count = self.chunk.byte_at(ip);
+                ip += 1;
+                self.call(arg_count)?;
+            },
+            OpCode::Jump => {
+                let distance = self.chunk.short_at(ip);
+                ip += 2 + distance as usize;
+            },
+            OpCode::JumpIfFalse => {
+                let distance = self.chunk.short_at(ip);
+                ip += 2;
+                let condition = self.stack.last().cloned().ok_or_else(underflow_error)?;
+                if !bool::try_from(condition).map_err(VMError::RuntimeError)? {
+                    ip += distance as usize;
+                }
+            },
+            OpCode::Pop => {
+                self.pop()?;
+            },
+            OpCode::Loop => {
+                let distance = self.chunk.short_at(ip) as usize;
+                ip = (ip + 2).checked_sub(distance).ok_or_else(|| {
+                    VMError::CompileError(format!(
+                        "loop at offset {} jumps before the start of the chunk",
+                        offset
+                    ))
+                })?;
+            },
+            OpCode::TypeOf => {
+                let value = self.pop()?;
+                self.stack.push(Value::from(self.heap.type_name(&value)));
+            },
+            OpCode::Is => {
+                let name = String::try_from(self.pop()?).map_err(VMError::RuntimeError)?;
+                let value = self.pop()?;
+                if !value::TYPE_NAMES.contains(&name.as_str()) {
+                    return Err(VMError::RuntimeError(format!("unknown type '{}'", name)));
+                }
+                self.stack.push(Value::Bool(self.heap.type_name(&value) == name));
+            },
+            OpCode::BitAnd | OpCode::BitOr | OpCode::BitXor => {
+                let b = self.pop_int()?;
+                let a = self.pop_int()?;
+                let result = match opcode {
+                    OpCode::BitAnd => a & b,
+                    OpCode::BitOr => a | b,
+                    _ => a ^ b
+                };
+                self.stack.push(Value::Int(result));
+            },
+            OpCode::ShiftLeft | OpCode::ShiftRight => {
+                let amount = shift_amount(self.pop_int()?)?;
+                let a = self.pop_int()?;
+                let result = match opcode {
+                    OpCode::ShiftLeft => a << amount,
+                    _ => a >> amount
+                };
+                self.stack.push(Value::Int(result));
+            },
+            OpCode::BitNot => {
+                let a = self.pop_int()?;
+                self.stack.push(Value::Int(!a));
+            },
+            OpCode::Equal => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                self.stack.push(Value::Bool(self.heap.equal(&a, &b)));
+            },
+            OpCode::Print | OpCode::PrintNoNewline => {
+                let value = self.pop()?;
+                let ending = if opcode == OpCode::Print { "\n" } else { "" };
+                let text = value.to_radix_string(self.print_radix)
+                    .unwrap_or_else(|| self.heap.show(&value).to_string());
+                self.print(&format!("{}{}", text, ending))?;
+            },
+            OpCode::SmallInt => {
+                let value = self.chunk.byte_at(ip) as i8;
+                ip += 1;
+                self.stack.push(Value::Int(value as i64));
+            },
+            OpCode::Format => {
+                let spec = String::try_from(self.pop()?).map_err(VMError::RuntimeError)?;
+                let value = self.pop()?;
+                let spec = format::parse_format_spec(&spec).map_err(VMError::RuntimeError)?;
+                self.stack.push(Value::Str(spec.apply(&value)));
+            },
+            OpCode::PrintStack => self.print_stack()?,
+            OpCode::Concat => {
+                self.collect_garbage();
+                let b = self.pop()?;
+                let a = self.pop()?;
+                let joined = concat(&mut self.heap, a, b)?;
+                self.stack.push(joined);
+            },
+            OpCode::MakeArray => {
+                let count = self.chunk.byte_at(ip) as usize;
+                ip += 1;
+                let top = self.stack.len();
+                if top < count {
+                    return Err(underflow_error());
+                }
+                self.collect_garbage();
+                let values = self.stack.split_off(top - count);
+                let array = self.heap.alloc(Obj::Array(values));
+                self.stack.push(Value::Obj(array));
+            },
+            OpCode::Sort => {
+                let values = match self.stack.last().ok_or_else(underflow_error)? {
+                    Value::Obj(handle) => match self.heap.get_mut(*handle) {
+                        Some(Obj::Array(values)) => values,
+                        None => return Err(missing_object_error(*handle))
+                    },
+                    other => {
+                        return Err(VMError::RuntimeError(format!("cannot sort {}", other)));
+                    }
+                };
+                value::sort_values(values).map_err(VMError::RuntimeError)?;
+            },
+            OpCode::Invalid(byte) => {
+                return Err(VMError::CompileError(format!(
+                    "invalid opcode {:#04X} at offset {} (line {})",
+                    byte,
+                    offset,
+                    self.chunk.get_line(offset)
+                )));
             }
         }
         
-        result
+        self.ip = ip;
+        Ok(false)
+    }
+    
+    /// Return the storage slot of the global named by
+    /// the constant at `name_index`
+    /// 
+    /// The slot is looked up by name the first time
+    /// and cached by constant index after that.
+    fn global_slot(&mut self, name_index: u8) -> Result<usize, VMError> {
+        let index = name_index as usize;
+        if let Some(Some(slot)) = self.global_cache.get(index) {
+            return Ok(*slot);
+        }
+        
+        let name = self.chunk.const_val(name_index);
+        let slot = match &name {
+            Value::Str(name) => self.global_slots.get(name).copied(),
+            _ => None
+        }.ok_or_else(|| VMError::RuntimeError(format!(
+            "undefined global '{}'",
+            name
+        )))?;
+        
+        if self.global_cache.len() <= index {
+            self.global_cache.resize(index + 1, None);
+        }
+        self.global_cache[index] = Some(slot);
+        Ok(slot)
+    }
+    
+    /// Call the value below the top `arg_count` values
+    /// on the stack, replacing it and its arguments with
+    /// the result
+    fn call(&mut self, arg_count: u8) -> Result<(), VMError> {
+        if self.stack.len() <= arg_count as usize {
+            return Err(underflow_error());
+        }
+        let callee_slot = self.stack.len() - 1 - arg_count as usize;
+        
+        match self.stack[callee_slot].clone() {
+            Value::Native(native) => {
+                if native.arity != arg_count {
+                    return Err(VMError::RuntimeError(format!(
+                        "{} expects {} arguments but got {}",
+                        native.name,
+                        native.arity,
+                        arg_count
+                    )));
+                }
+                let result = (native.function)(&self.stack[callee_slot + 1..])
+                    .map_err(|message| VMError::RuntimeError(format!(
+                        "{}: {}",
+                        native.name,
+                        message
+                    )))?;
+                self.stack.truncate(callee_slot);
+                self.stack.push(result);
+                Ok(())
+            },
+            other => Err(VMError::RuntimeError(format!("{} is not callable", other)))
+        }
+    }
+    
+    /// Free the objects that can't be reached from the
+    /// stack or the globals, if the heap has grown
+    /// enough to need it
+    /// 
+    /// This runs before an instruction allocates, while
+    /// its operands are still on the stack.
+    fn collect_garbage(&mut self) {
+        if self.heap.should_collect() {
+            self.heap.collect_from(self.stack.iter().chain(&self.globals));
+        }
+    }
+    
+    /// Read a line from the input without its line
+    /// ending, or `Value::Nil` at the end of the input
+    fn read_line(&mut self) -> Result<Value, VMError> {
+        let mut line = String::new();
+        let read = self.input.read_line(&mut line).map_err(|error| {
+            VMError::RuntimeError(format!("could not read line: {}", error))
+        })?;
+        
+        if read == 0 {
+            Ok(Value::Nil)
+        } else {
+            let length = line.trim_end_matches(&['\r', '\n'][..]).len();
+            line.truncate(length);
+            Ok(Value::Str(line))
+        }
+    }
+    
+    /// Write the values on the stack, from bottom to
+    /// top, to the output without changing the stack
+    fn print_stack(&mut self) -> Result<(), VMError> {
+        let mut line = String::new();
+        for value in &self.stack {
+            line.push_str(&format!("[ {} ]", self.heap.show(value)));
+        }
+        line.push('\n');
+        self.print(&line)
+    }
+    
+    /// Write text to the output
+    fn print(&mut self, text: &str) -> Result<(), VMError> {
+        self.output.write_all(text.as_bytes()).map_err(|error| {
+            VMError::RuntimeError(format!("could not print: {}", error))
+        })
+    }
+    
+    /// Pop the value on top of the stack
+    fn pop(&mut self) -> Result<Value, VMError> {
+        self.stack.pop().ok_or_else(underflow_error)
+    }
+    
+    /// Pop two values and push the result of
+    /// applying an arithmetic operator to them
+    /// 
+    /// In strict mode, an int and a float can't be
+    /// mixed.
+    fn arithmetic(&mut self, op: ArithmeticOp) -> Result<(), VMError> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        if self.strict {
+            if let (Value::Int(_), Value::Float(_)) | (Value::Float(_), Value::Int(_)) = (&a, &b) {
+                return Err(VMError::RuntimeError(format!(
+                    "cannot mix {} and {} in strict mode",
+                    a.type_name(),
+                    b.type_name()
+                )));
+            }
+        }
+        let result = a.arithmetic(b, op).map_err(VMError::RuntimeError)?;
+        self.stack.push(result);
+        Ok(())
+    }
+    
+    /// Pop the value on top of the stack, which
+    /// must be an integer
+    fn pop_int(&mut self) -> Result<i64, VMError> {
+        i64::try_from(self.pop()?).map_err(VMError::RuntimeError)
     }
 }
 
-type VMResult = Result<(), VMError>;
+/// Return the element of a collection at an index
+/// 
+/// Strings are indexed by Unicode scalar value rather
+/// than by byte, so an index can never land in the
+/// middle of a character.
+fn get_index(heap: &Heap, collection: Value, index: i64) -> Result<Value, VMError> {
+    let out_of_range = |length: usize| VMError::RuntimeError(format!(
+        "index {} out of range for length {}",
+        index,
+        length
+    ));
+    
+    match collection {
+        Value::Str(string) => {
+            if index < 0 {
+                return Err(out_of_range(string.chars().count()));
+            }
+            string.chars()
+                .nth(index as usize)
+                .map(Value::Char)
+                .ok_or_else(|| out_of_range(string.chars().count()))
+        },
+        Value::Bytes(bytes) => {
+            if index < 0 || index as usize >= bytes.len() {
+                Err(out_of_range(bytes.len()))
+            } else {
+                Ok(Value::Int(bytes[index as usize] as i64))
+            }
+        },
+        Value::Tuple(mut values) => {
+            if index < 0 || index as usize >= values.len() {
+                Err(out_of_range(values.len()))
+            } else {
+                Ok(values.swap_remove(index as usize))
+            }
+        },
+        Value::Obj(handle) => match resolve(heap, handle)? {
+            Obj::Array(values) => {
+                if index < 0 || index as usize >= values.len() {
+                    Err(out_of_range(values.len()))
+                } else {
+                    Ok(values[index as usize].clone())
+                }
+            }
+        },
+        other => Err(VMError::RuntimeError(format!("{} cannot be indexed", other)))
+    }
+}
+
+/// Join two strings, two byte sequences, or two
+/// arrays, allocating the joined array on the heap
+fn concat(heap: &mut Heap, a: Value, b: Value) -> Result<Value, VMError> {
+    match (a, b) {
+        (Value::Str(a), Value::Str(b)) => Ok(Value::Str(a + &b)),
+        (Value::Bytes(a), Value::Bytes(b)) => {
+            Ok(Value::Bytes(a.iter().chain(b.iter()).cloned().collect()))
+        },
+        (Value::Obj(a), Value::Obj(b)) => {
+            let joined = match (resolve(heap, a)?, resolve(heap, b)?) {
+                (Obj::Array(a), Obj::Array(b)) => a.iter().chain(b).cloned().collect()
+            };
+            Ok(Value::Obj(heap.alloc(Obj::Array(joined))))
+        },
+        (a, b) => Err(VMError::RuntimeError(format!("cannot concatenate {} and {}", a, b)))
+    }
+}
 
-enum VMError {
-    CompileError,
-    RuntimeError
+/// Look up the object a handle refers to
+fn resolve(heap: &Heap, handle: ObjHandle) -> Result<&Obj, VMError> {
+    heap.get(handle).ok_or_else(|| missing_object_error(handle))
+}
+
+/// Check that an integer can be used to shift a
+/// 64-bit integer
+fn shift_amount(amount: i64) -> Result<u32, VMError> {
+    if (0..64).contains(&amount) {
+        Ok(amount as u32)
+    } else {
+        Err(VMError::RuntimeError(format!("cannot shift by {} bits", amount)))
+    }
+}
+
+/// The error produced when an instruction needs
+/// more values than are on the stack
+fn underflow_error() -> VMError {
+    VMError::RuntimeError("stack underflow".to_string())
+}
+
+/// The error produced when a handle doesn't refer
+/// to an object on the heap
+fn missing_object_error(handle: ObjHandle) -> VMError {
+    VMError::RuntimeError(format!("object #{} does not exist", handle.index()))
+}
+
+/// The error produced when integer arithmetic
+/// overflows
+fn overflow_error() -> VMError {
+    VMError::RuntimeError("integer overflow".to_string())
+}
+
+/// How a call to `VM::run_for` ended
+#[derive(Debug, PartialEq)]
+pub enum RunState {
+    /// The chunk returned
+    Halted,
+    
+    /// The step budget ran out before the chunk
+    /// returned
+    Yielded,
+    
+    /// An instruction failed
+    Errored(VMError)
+}
+
+/// The result of running the VM
+pub type VMResult = Result<(), VMError>;
+
+/// An error produced while running the VM, along
+/// with a message describing what went wrong
+#[derive(Debug, PartialEq)]
+pub enum VMError {
+    CompileError(String),
+    RuntimeError(String)
+}
+
+impl fmt::Display for VMError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VMError::CompileError(message) => write!(f, "compile error: {}", message),
+            VMError::RuntimeError(message) => write!(f, "runtime error: {}", message)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::io::Cursor;
+    use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+    use std::thread;
+    use chunk::Chunk;
+    
+    #[test]
+    fn invalid_opcode_names_offset() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Int(1))
+            .write(1, 1)
+            .write(0, 1)
+            .write(0x2A, 3);
+        let message = match VM::new(chunk).run() {
+            Err(VMError::CompileError(message)) => message,
+            other => panic!("expected a compile error, got {:?}", other)
+        };
+        
+        assert_eq!("invalid opcode 0x2A at offset 2 (line 3)", message);
+    }
+    
+    #[test]
+    fn default_stack_capacity() {
+        assert!(VM::new(Chunk::new()).stack.capacity() >= DEFAULT_STACK_CAPACITY);
+    }
+    
+    #[test]
+    fn reserve_stack_capacity() {
+        let vm = VM::with_capacity(Chunk::new(), 10_000);
+        
+        assert!(vm.stack.capacity() >= 10_000);
+    }
+    
+    #[test]
+    fn trace_hook_sees_each_instruction() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Int(1))
+            .write(1, 1)
+            .write(0, 1)
+            .write(2, 1)
+            .write(3, 1)
+            .write(0, 1);
+        let events = Rc::new(RefCell::new(vec![]));
+        let recorded = Rc::clone(&events);
+        let mut vm = VM::new(chunk);
+        vm.set_trace_hook(Box::new(move |event| {
+            recorded.borrow_mut().push((event.ip, event.opcode, event.stack.to_vec()));
+        }));
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(
+            vec![
+                (0, OpCode::Constant, vec![]),
+                (2, OpCode::Inc, vec![Value::Int(1)]),
+                (3, OpCode::Dec, vec![Value::Int(2)]),
+                (4, OpCode::Return, vec![Value::Int(1)])
+            ],
+            *events.borrow()
+        );
+    }
+    
+    #[test]
+    fn increment_int() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Int(41))
+            .write(1, 1)
+            .write(0, 1)
+            .write(2, 1)
+            .write(0, 1);
+        let mut vm = VM::new(chunk);
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(vec![Value::Int(42)], vm.stack);
+    }
+    
+    #[test]
+    fn decrement_int() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Int(43))
+            .write(1, 1)
+            .write(0, 1)
+            .write(3, 1)
+            .write(0, 1);
+        let mut vm = VM::new(chunk);
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(vec![Value::Int(42)], vm.stack);
+    }
+    
+    #[test]
+    fn make_exclusive_range() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Int(0))
+            .add_constant(Value::Int(5))
+            .write(1, 1)
+            .write(0, 1)
+            .write(1, 1)
+            .write(1, 1)
+            .write(4, 1)
+            .write(0, 1)
+            .write(0, 1);
+        let mut vm = VM::new(chunk);
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(vec![Value::Range { start: 0, end: 5, inclusive: false }], vm.stack);
+        assert_eq!("0..5", vm.stack[0].to_string());
+    }
+    
+    #[test]
+    fn make_inclusive_range() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Int(0))
+            .add_constant(Value::Int(5))
+            .write(1, 1)
+            .write(0, 1)
+            .write(1, 1)
+            .write(1, 1)
+            .write(4, 1)
+            .write(1, 1)
+            .write(0, 1);
+        let mut vm = VM::new(chunk);
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(vec![Value::Range { start: 0, end: 5, inclusive: true }], vm.stack);
+        assert_eq!("0..=5", vm.stack[0].to_string());
+    }
+    
+    #[test]
+    fn make_range_from_non_int() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Int(0))
+            .add_constant(Value::Range { start: 0, end: 1, inclusive: false })
+            .write(1, 1)
+            .write(0, 1)
+            .write(1, 1)
+            .write(1, 1)
+            .write(4, 1)
+            .write(0, 1)
+            .write(0, 1);
+        
+        assert_eq!(
+            Err(VMError::RuntimeError("expected an integer, found 0..1".to_string())),
+            VM::new(chunk).run()
+        );
+    }
+    
+    #[test]
+    fn unpack_tuple() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Tuple(vec![Value::Int(1), Value::Int(2)]))
+            .write(1, 1)
+            .write(0, 1)
+            .write(5, 1)
+            .write(2, 1)
+            .write(0, 1);
+        let mut vm = VM::new(chunk);
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(vec![Value::Int(1), Value::Int(2)], vm.stack);
+    }
+    
+    #[test]
+    fn unpack_arity_mismatch() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Tuple(vec![Value::Int(1), Value::Int(2)]))
+            .write(1, 1)
+            .write(0, 1)
+            .write(5, 1)
+            .write(3, 1)
+            .write(0, 1);
+        
+        assert_eq!(
+            Err(VMError::RuntimeError("expected a tuple of 3 values, found 2".to_string())),
+            VM::new(chunk).run()
+        );
+    }
+    
+    #[test]
+    fn read_line_from_input() {
+        let chunk = Chunk::new().write(6, 1).write(6, 1).write(0, 1);
+        let mut vm = VM::new(chunk);
+        vm.set_input(Box::new(Cursor::new("hello\n")));
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(vec![Value::Str("hello".to_string()), Value::Nil], vm.stack);
+    }
+    
+    #[test]
+    fn string_length() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Str("abc".to_string()))
+            .add_constant(Value::Str("café".to_string()))
+            .write(1, 1)
+            .write(0, 1)
+            .write(7, 1)
+            .write(1, 1)
+            .write(1, 1)
+            .write(7, 1)
+            .write(0, 1);
+        let mut vm = VM::new(chunk);
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(vec![Value::Int(3), Value::Int(4)], vm.stack);
+    }
+    
+    #[test]
+    fn length_of_non_collection() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Int(3))
+            .write(1, 1)
+            .write(0, 1)
+            .write(7, 1)
+            .write(0, 1);
+        
+        assert_eq!(
+            Err(VMError::RuntimeError("3 has no length".to_string())),
+            VM::new(chunk).run()
+        );
+    }
+    
+    #[test]
+    fn index_string() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Str("café".to_string()))
+            .add_constant(Value::Int(3))
+            .write(1, 1)
+            .write(0, 1)
+            .write(1, 1)
+            .write(1, 1)
+            .write(8, 1)
+            .write(0, 1);
+        let mut vm = VM::new(chunk);
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(vec![Value::Char('é')], vm.stack);
+    }
+    
+    #[test]
+    fn index_string_out_of_range() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Str("café".to_string()))
+            .add_constant(Value::Int(4))
+            .write(1, 1)
+            .write(0, 1)
+            .write(1, 1)
+            .write(1, 1)
+            .write(8, 1)
+            .write(0, 1);
+        
+        assert_eq!(
+            Err(VMError::RuntimeError("index 4 out of range for length 4".to_string())),
+            VM::new(chunk).run()
+        );
+    }
+    
+    #[test]
+    fn index_bytes() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Bytes(Arc::from(&b"\xffab"[..])))
+            .add_constant(Value::Int(0))
+            .write(1, 1)
+            .write(0, 1)
+            .write(1, 1)
+            .write(1, 1)
+            .write(8, 1)
+            .write(0, 1);
+        let mut vm = VM::new(chunk);
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(vec![Value::Int(255)], vm.stack);
+    }
+    
+    #[test]
+    fn concatenate_bytes() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Bytes(Arc::from(&b"ab"[..])))
+            .add_constant(Value::Bytes(Arc::from(&b"cd"[..])))
+            .write(1, 1)
+            .write(0, 1)
+            .write(1, 1)
+            .write(1, 1)
+            .write(16, 1)
+            .write(7, 1)
+            .write(0, 1);
+        let mut vm = VM::new(chunk);
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(vec![Value::Int(4)], vm.stack);
+    }
+    
+    #[test]
+    fn concatenate_strings() {
+        let chunk = Chunk::new()
+            .add_constant(Value::from("ab"))
+            .add_constant(Value::from("cd"))
+            .write(1, 1)
+            .write(0, 1)
+            .write(1, 1)
+            .write(1, 1)
+            .write(16, 1)
+            .write(0, 1);
+        let mut vm = VM::new(chunk);
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(vec![Value::from("abcd")], vm.stack);
+    }
+    
+    #[test]
+    fn concatenate_bytes_and_string() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Bytes(Arc::from(&b"ab"[..])))
+            .add_constant(Value::Str("cd".to_string()))
+            .write(1, 1)
+            .write(0, 1)
+            .write(1, 1)
+            .write(1, 1)
+            .write(16, 1)
+            .write(0, 1);
+        
+        assert_eq!(
+            Err(VMError::RuntimeError("cannot concatenate b\"ab\" and cd".to_string())),
+            VM::new(chunk).run()
+        );
+    }
+    
+    #[test]
+    fn add_strings() {
+        let chunk = Chunk::new()
+            .add_constant(Value::from("ab"))
+            .add_constant(Value::from("cd"))
+            .write(1, 1)
+            .write(0, 1)
+            .write(1, 1)
+            .write(1, 1)
+            .write(9, 1)
+            .write(0, 1);
+        
+        assert_eq!(
+            Err(VMError::RuntimeError("cannot add ab and cd".to_string())),
+            VM::new(chunk).run()
+        );
+    }
+    
+    #[test]
+    fn swap_before_subtract() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Int(1))
+            .add_constant(Value::Int(2))
+            .write(1, 1)
+            .write(0, 1)
+            .write(1, 1)
+            .write(1, 1)
+            .write(11, 1)
+            .write(10, 1)
+            .write(0, 1);
+        let mut vm = VM::new(chunk);
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(vec![Value::Int(1)], vm.stack);
+    }
+    
+    #[test]
+    fn swap_single_value() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Int(1))
+            .write(1, 1)
+            .write(0, 1)
+            .write(11, 1)
+            .write(0, 1);
+        
+        assert_eq!(
+            Err(VMError::RuntimeError("stack underflow".to_string())),
+            VM::new(chunk).run()
+        );
+    }
+    
+    /// Define a `sqrt` native that takes a float
+    fn define_sqrt(vm: &mut VM) {
+        vm.define_native("sqrt", 1, |args| match args[0] {
+            Value::Float(x) if x >= 0.0 => Ok(Value::Float(x.sqrt())),
+            ref other => Err(format!("cannot take the square root of {}", other))
+        });
+    }
+    
+    #[test]
+    fn call_native() {
+        let chunk = Chunk::new()
+            .add_constant(Value::from("sqrt"))
+            .add_constant(Value::Float(16.0))
+            .write(12, 1)
+            .write(0, 1)
+            .write(1, 1)
+            .write(1, 1)
+            .write(13, 1)
+            .write(1, 1)
+            .write(0, 1);
+        let mut vm = VM::new(chunk);
+        define_sqrt(&mut vm);
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(vec![Value::Float(4.0)], vm.stack);
+    }
+    
+    #[test]
+    fn call_native_with_wrong_arity() {
+        let chunk = Chunk::new()
+            .add_constant(Value::from("sqrt"))
+            .write(12, 1)
+            .write(0, 1)
+            .write(13, 1)
+            .write(0, 1)
+            .write(0, 1);
+        let mut vm = VM::new(chunk);
+        define_sqrt(&mut vm);
+        
+        assert_eq!(
+            Err(VMError::RuntimeError("sqrt expects 1 arguments but got 0".to_string())),
+            vm.run()
+        );
+    }
+    
+    #[test]
+    fn native_error() {
+        let chunk = Chunk::new()
+            .add_constant(Value::from("sqrt"))
+            .add_constant(Value::Float(-1.0))
+            .write(12, 1)
+            .write(0, 1)
+            .write(1, 1)
+            .write(1, 1)
+            .write(13, 1)
+            .write(1, 1)
+            .write(0, 1);
+        let mut vm = VM::new(chunk);
+        define_sqrt(&mut vm);
+        
+        assert_eq!(
+            Err(VMError::RuntimeError("sqrt: cannot take the square root of -1.0".to_string())),
+            vm.run()
+        );
+    }
+    
+    #[test]
+    fn undefined_global() {
+        let chunk = Chunk::new()
+            .add_constant(Value::from("sqrt"))
+            .write(12, 1)
+            .write(0, 1)
+            .write(0, 1);
+        
+        assert_eq!(
+            Err(VMError::RuntimeError("undefined global 'sqrt'".to_string())),
+            VM::new(chunk).run()
+        );
+    }
+    
+    #[test]
+    fn clock_native() {
+        struct FixedClock;
+        
+        impl Clock for FixedClock {
+            fn now(&self) -> f64 {
+                12.5
+            }
+        }
+        
+        let chunk = Chunk::new()
+            .add_constant(Value::from("clock"))
+            .write(12, 1)
+            .write(0, 1)
+            .write(13, 1)
+            .write(0, 1)
+            .write(0, 1);
+        let mut vm = VM::new(chunk);
+        vm.set_clock(Arc::new(FixedClock));
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(vec![Value::Float(12.5)], vm.stack);
+    }
+    
+    #[test]
+    fn jump_over_code() {
+        let mut chunk = Chunk::new().add_constant(Value::Int(1));
+        chunk.push_byte(1, 1);
+        chunk.push_byte(0, 1);
+        chunk.push_byte(14, 1);
+        chunk.push_byte(0xff, 1);
+        chunk.push_byte(0xff, 1);
+        chunk.push_byte(2, 1);
+        chunk.push_byte(2, 1);
+        chunk.patch_jump(2).unwrap();
+        chunk.push_byte(3, 1);
+        chunk.push_byte(0, 1);
+        let mut vm = VM::new(chunk);
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(vec![Value::Int(0)], vm.stack);
+    }
+    
+    #[test]
+    fn increment_overflow() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Int(i64::MAX))
+            .write(1, 1)
+            .write(0, 1)
+            .write(2, 1)
+            .write(0, 1);
+        
+        assert_eq!(
+            Err(VMError::RuntimeError("integer overflow".to_string())),
+            VM::new(chunk).run()
+        );
+    }
+    
+    /// An output sink that can be read after the VM
+    /// has written to it
+    #[derive(Clone, Default)]
+    struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+    
+    impl SharedOutput {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+    
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+    
+    #[test]
+    fn print_stack_keeps_values() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Int(1))
+            .add_constant(Value::from("two"))
+            .write(1, 1)
+            .write(0, 1)
+            .write(1, 1)
+            .write(1, 1)
+            .write(15, 2)
+            .write(0, 2);
+        let output = SharedOutput::default();
+        let mut vm = VM::new(chunk);
+        vm.set_output(Box::new(output.clone()));
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!("[ 1 ][ two ]\n", output.contents());
+        assert_eq!(vec![Value::Int(1), Value::from("two")], vm.stack);
+    }
+    
+    #[test]
+    fn interpret_verified_result() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Int(41))
+            .write(1, 1)
+            .write(0, 1)
+            .write(2, 1)
+            .write(0, 1);
+        
+        assert_eq!(Ok(Value::Int(42)), VM::new(chunk).interpret_verified());
+    }
+    
+    #[test]
+    fn interpret_verified_rejects_before_running() {
+        let chunk = Chunk::new()
+            .add_constant(Value::from("record"))
+            .write(12, 1)
+            .write(0, 1)
+            .write(13, 1)
+            .write(0, 1)
+            .write(1, 2)
+            .write(7, 2)
+            .write(0, 2);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&calls);
+        let mut vm = VM::new(chunk);
+        vm.define_native("record", 0, move |_| {
+            counted.fetch_add(1, AtomicOrdering::SeqCst);
+            Ok(Value::Nil)
+        });
+        
+        assert!(matches!(vm.interpret_verified(), Err(VMError::CompileError(_))));
+        assert_eq!(0, calls.load(AtomicOrdering::SeqCst));
+    }
+    
+    #[test]
+    fn count_opcodes() {
+        let mut chunk = Chunk::new().add_constant(Value::Int(1));
+        chunk.push_byte(1, 1);
+        chunk.push_byte(0, 1);
+        for _ in 0..3 {
+            chunk.push_byte(1, 1);
+            chunk.push_byte(0, 1);
+            chunk.push_byte(9, 1);
+        }
+        chunk.push_byte(0, 1);
+        let mut vm = VM::new(chunk);
+        vm.set_profiling(true);
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(vec![Value::Int(4)], vm.stack);
+        
+        let counts = vm.opcode_counts();
+        assert_eq!(Some(&3), counts.get(&OpCode::Add));
+        assert_eq!(Some(&4), counts.get(&OpCode::Constant));
+        assert_eq!(Some(&1), counts.get(&OpCode::Return));
+        assert_eq!(3, counts.len());
+    }
+    
+    #[test]
+    fn profiling_off_by_default() {
+        let mut vm = VM::new(Chunk::test());
+        
+        assert_eq!(Ok(()), vm.run());
+        assert!(vm.opcode_counts().is_empty());
+    }
+    
+    /// Assemble `if <condition> { 1 } else { 2 }`,
+    /// with the condition popped on both branches
+    fn if_else_chunk(condition: bool) -> Chunk {
+        Chunk::new()
+            .add_constant(Value::Bool(condition))
+            .add_constant(Value::Int(1))
+            .add_constant(Value::Int(2))
+            .write(1, 1)
+            .write(0, 1)
+            .write(17, 1)
+            .write(0, 1)
+            .write(6, 1)
+            .write(18, 1)
+            .write(1, 1)
+            .write(1, 1)
+            .write(14, 1)
+            .write(0, 1)
+            .write(3, 1)
+            .write(18, 1)
+            .write(1, 1)
+            .write(2, 1)
+            .write(0, 1)
+    }
+    
+    #[test]
+    fn if_else_true_branch() {
+        let mut vm = VM::new(if_else_chunk(true));
+        
+        assert_eq!(Ok(Value::Int(1)), vm.interpret_verified());
+        assert_eq!(vec![Value::Int(1)], vm.stack);
+    }
+    
+    #[test]
+    fn if_else_false_branch() {
+        let mut vm = VM::new(if_else_chunk(false));
+        
+        assert_eq!(Ok(Value::Int(2)), vm.interpret_verified());
+        assert_eq!(vec![Value::Int(2)], vm.stack);
+    }
+    
+    #[test]
+    fn jump_if_false_needs_bool() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Int(0))
+            .write(1, 1)
+            .write(0, 1)
+            .write(17, 1)
+            .write(0, 1)
+            .write(0, 1)
+            .write(0, 1);
+        
+        assert_eq!(
+            Err(VMError::RuntimeError("expected a bool, found 0".to_string())),
+            VM::new(chunk).run()
+        );
+    }
+    
+    #[test]
+    fn define_get_and_set_global() {
+        let chunk = Chunk::new()
+            .add_constant(Value::from("x"))
+            .add_constant(Value::Int(1))
+            .add_constant(Value::Int(5))
+            .write(1, 1)
+            .write(1, 1)
+            .write(19, 1)
+            .write(0, 1)
+            .write(12, 2)
+            .write(0, 2)
+            .write(1, 3)
+            .write(2, 3)
+            .write(20, 3)
+            .write(0, 3)
+            .write(18, 3)
+            .write(12, 4)
+            .write(0, 4)
+            .write(0, 4);
+        let mut vm = VM::new(chunk);
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(vec![Value::Int(1), Value::Int(5)], vm.stack);
+    }
+    
+    #[test]
+    fn redefined_global_keeps_cached_slot() {
+        let chunk = Chunk::new()
+            .add_constant(Value::from("x"))
+            .add_constant(Value::Int(1))
+            .add_constant(Value::Int(2))
+            .write(1, 1)
+            .write(1, 1)
+            .write(19, 1)
+            .write(0, 1)
+            .write(12, 2)
+            .write(0, 2)
+            .write(1, 3)
+            .write(2, 3)
+            .write(19, 3)
+            .write(0, 3)
+            .write(12, 4)
+            .write(0, 4)
+            .write(0, 4);
+        let mut vm = VM::new(chunk);
+        vm.set_allow_redefinition(true);
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(vec![Value::Int(1), Value::Int(2)], vm.stack);
+    }
+    
+    #[test]
+    fn redefine_global_is_an_error() {
+        let chunk = Chunk::new()
+            .add_constant(Value::from("x"))
+            .add_constant(Value::Int(1))
+            .write(1, 1)
+            .write(1, 1)
+            .write(19, 1)
+            .write(0, 1)
+            .write(1, 2)
+            .write(1, 2)
+            .write(19, 2)
+            .write(0, 2)
+            .write(0, 2);
+        
+        assert_eq!(
+            Err(VMError::RuntimeError("global 'x' already defined".to_string())),
+            VM::new(chunk).run()
+        );
+    }
+    
+    #[test]
+    fn redefine_native() {
+        let chunk = Chunk::new()
+            .add_constant(Value::from("clock"))
+            .add_constant(Value::Int(1))
+            .write(1, 1)
+            .write(1, 1)
+            .write(19, 1)
+            .write(0, 1)
+            .write(12, 2)
+            .write(0, 2)
+            .write(0, 2);
+        let mut vm = VM::new(chunk);
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(vec![Value::Int(1)], vm.stack);
+    }
+    
+    #[test]
+    fn set_undefined_global() {
+        let chunk = Chunk::new()
+            .add_constant(Value::from("y"))
+            .add_constant(Value::Int(1))
+            .write(1, 1)
+            .write(1, 1)
+            .write(20, 1)
+            .write(0, 1)
+            .write(0, 1);
+        
+        assert_eq!(
+            Err(VMError::RuntimeError("undefined global 'y'".to_string())),
+            VM::new(chunk).run()
+        );
+    }
+    
+    #[test]
+    fn type_of_values() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Int(1))
+            .add_constant(Value::from("x"))
+            .write(1, 1)
+            .write(0, 1)
+            .write(22, 1)
+            .write(1, 2)
+            .write(1, 2)
+            .write(22, 2)
+            .write(0, 2);
+        let mut vm = VM::new(chunk);
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(vec![Value::from("int"), Value::from("string")], vm.stack);
+    }
+    
+    /// Assemble a chunk applying a binary opcode to
+    /// two integers
+    fn binary_chunk(a: i64, opcode: u8, b: i64) -> Chunk {
+        Chunk::new()
+            .add_constant(Value::Int(a))
+            .add_constant(Value::Int(b))
+            .write(1, 1)
+            .write(0, 1)
+            .write(1, 1)
+            .write(1, 1)
+            .write(opcode, 1)
+            .write(0, 1)
+    }
+    
+    #[test]
+    fn bitwise_and() {
+        assert_eq!(Ok(Value::Int(2)), VM::new(binary_chunk(6, 23, 3)).interpret_verified());
+    }
+    
+    #[test]
+    fn bitwise_or_xor_not() {
+        assert_eq!(Ok(Value::Int(7)), VM::new(binary_chunk(6, 24, 3)).interpret_verified());
+        assert_eq!(Ok(Value::Int(5)), VM::new(binary_chunk(6, 25, 3)).interpret_verified());
+        
+        let chunk = Chunk::new()
+            .add_constant(Value::Int(0))
+            .write(1, 1)
+            .write(0, 1)
+            .write(28, 1)
+            .write(0, 1);
+        assert_eq!(Ok(Value::Int(-1)), VM::new(chunk).interpret_verified());
+    }
+    
+    #[test]
+    fn shift_left() {
+        assert_eq!(Ok(Value::Int(16)), VM::new(binary_chunk(1, 26, 4)).interpret_verified());
+    }
+    
+    #[test]
+    fn shift_right_keeps_sign() {
+        assert_eq!(Ok(Value::Int(-4)), VM::new(binary_chunk(-16, 27, 2)).interpret_verified());
+    }
+    
+    #[test]
+    fn shift_out_of_range() {
+        assert_eq!(
+            Err(VMError::RuntimeError("cannot shift by 64 bits".to_string())),
+            VM::new(binary_chunk(1, 26, 64)).run()
+        );
+        assert!(VM::new(binary_chunk(1, 27, -1)).run().is_err());
+    }
+    
+    /// Assemble a chunk comparing two values with
+    /// `OP_EQUAL`
+    fn equal_chunk(a: Value, b: Value) -> Chunk {
+        Chunk::new()
+            .add_constant(a)
+            .add_constant(b)
+            .write(1, 1)
+            .write(0, 1)
+            .write(1, 1)
+            .write(1, 1)
+            .write(29, 1)
+            .write(0, 1)
+    }
+    
+    #[test]
+    fn equal_nested_tuples() {
+        let tuple = || Value::Tuple(vec![
+            Value::Int(1),
+            Value::Tuple(vec![Value::from("a"), Value::Bool(true)])
+        ]);
+        
+        assert_eq!(Ok(Value::Bool(true)), VM::new(equal_chunk(tuple(), tuple())).interpret_verified());
+    }
+    
+    #[test]
+    fn unequal_nested_tuples() {
+        let a = Value::Tuple(vec![Value::Int(1), Value::Tuple(vec![Value::from("a")])]);
+        let b = Value::Tuple(vec![Value::Int(1), Value::Tuple(vec![Value::from("b")])]);
+        
+        assert_eq!(Ok(Value::Bool(false)), VM::new(equal_chunk(a, b)).interpret_verified());
+    }
+    
+    /// Assemble a chunk that makes the array
+    /// `[1, [2, last]]` twice and compares them
+    fn equal_arrays_chunk(last: i8) -> Chunk {
+        let array = [32, 1, 32, 2, 32, 3, 39, 2, 39, 2];
+        let other = [32, 1, 32, 2, 32, last as u8, 39, 2, 39, 2];
+        array.iter()
+            .chain(&other)
+            .chain(&[29, 0])
+            .fold(Chunk::new(), |chunk, &byte| chunk.write(byte, 1))
+    }
+    
+    #[test]
+    fn equal_nested_arrays() {
+        assert_eq!(Ok(Value::Bool(true)), VM::new(equal_arrays_chunk(3)).interpret_verified());
+    }
+    
+    #[test]
+    fn unequal_arrays() {
+        assert_eq!(Ok(Value::Bool(false)), VM::new(equal_arrays_chunk(4)).interpret_verified());
+    }
+    
+    #[test]
+    fn equal_self_referential_arrays() {
+        let chunk = Chunk::new()
+            .add_constant(Value::from("a"))
+            .add_constant(Value::from("b"))
+            .write(12, 1)
+            .write(0, 1)
+            .write(12, 1)
+            .write(1, 1)
+            .write(29, 1)
+            .write(12, 2)
+            .write(0, 2)
+            .write(35, 2)
+            .write(1, 2)
+            .write(29, 2)
+            .write(0, 2);
+        let mut vm = VM::new(chunk);
+        for name in ["a", "b"] {
+            let array = vm.heap.alloc(Obj::Array(vec![Value::Int(1)]));
+            if let Some(Obj::Array(values)) = vm.heap.get_mut(array) {
+                values.push(Value::Obj(array));
+            }
+            vm.define_global(name, Value::Obj(array));
+        }
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(vec![Value::Bool(true), Value::Bool(true)], vm.stack);
+    }
+    
+    #[test]
+    fn run_arith_add() {
+        assert_eq!(Ok(Value::Int(3)), VM::new(Chunk::arith_add(1, 2)).interpret_verified());
+    }
+    
+    #[test]
+    fn run_loop_countdown() {
+        let mut vm = VM::new(Chunk::loop_countdown(5));
+        vm.set_profiling(true);
+        
+        assert_eq!(Ok(Value::Int(0)), vm.interpret_verified());
+        assert_eq!(Some(&5), vm.opcode_counts().get(&OpCode::Dec));
+        assert_eq!(Some(&5), vm.opcode_counts().get(&OpCode::Loop));
+    }
+    
+    #[test]
+    fn print_with_and_without_newline() {
+        let chunk = Chunk::new()
+            .add_constant(Value::from("a"))
+            .add_constant(Value::Int(1))
+            .write(1, 1)
+            .write(0, 1)
+            .write(31, 1)
+            .write(1, 2)
+            .write(1, 2)
+            .write(31, 2)
+            .write(1, 3)
+            .write(0, 3)
+            .write(30, 3)
+            .write(0, 3);
+        let output = SharedOutput::default();
+        let mut vm = VM::new(chunk);
+        vm.set_output(Box::new(output.clone()));
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!("a1a\n", output.contents());
+        assert!(vm.stack.is_empty());
+    }
+    
+    #[test]
+    fn small_ints() {
+        let chunk = Chunk::new()
+            .write(32, 1)
+            .write(42, 1)
+            .write(32, 1)
+            .write(-5i8 as u8, 1)
+            .write(0, 1);
+        let mut vm = VM::new(chunk);
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(vec![Value::Int(42), Value::Int(-5)], vm.stack);
+    }
+    
+    #[test]
+    fn format_value() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Int(42))
+            .add_constant(Value::from("{:>5}"))
+            .write(1, 1)
+            .write(0, 1)
+            .write(1, 1)
+            .write(1, 1)
+            .write(33, 1)
+            .write(0, 1);
+        
+        assert_eq!(Ok(Value::from("   42")), VM::new(chunk).interpret_verified());
+    }
+    
+    #[test]
+    fn radix_natives() {
+        for (name, expected) in [("hex", "0xff"), ("bin", "0b11111111")] {
+            let chunk = Chunk::new()
+                .add_constant(Value::from(name))
+                .add_constant(Value::Int(255))
+                .write(12, 1)
+                .write(0, 1)
+                .write(1, 1)
+                .write(1, 1)
+                .write(13, 1)
+                .write(1, 1)
+                .write(0, 1);
+            let mut vm = VM::new(chunk);
+            
+            assert_eq!(Ok(()), vm.run());
+            assert_eq!(vec![Value::from(expected)], vm.stack);
+        }
+    }
+    
+    #[test]
+    fn print_radix() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Int(255))
+            .add_constant(Value::Float(0.5))
+            .write(1, 1)
+            .write(0, 1)
+            .write(30, 1)
+            .write(1, 1)
+            .write(1, 1)
+            .write(30, 1)
+            .write(0, 1);
+        let output = SharedOutput::default();
+        let mut vm = VM::new(chunk);
+        vm.set_output(Box::new(output.clone()));
+        vm.set_print_radix(Radix::Hex);
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!("0xff\n0.5\n", output.contents());
+    }
+    
+    /// A chunk that tests whether an integer has the
+    /// type named `name`
+    fn is_chunk(name: &str) -> Chunk {
+        Chunk::new()
+            .add_constant(Value::from(name))
+            .write(32, 1)
+            .write(1, 1)
+            .write(1, 1)
+            .write(0, 1)
+            .write(34, 1)
+            .write(0, 1)
+    }
+    
+    #[test]
+    fn is_type() {
+        assert_eq!(Ok(Value::Bool(true)), VM::new(is_chunk("int")).interpret_verified());
+        assert_eq!(Ok(Value::Bool(false)), VM::new(is_chunk("string")).interpret_verified());
+    }
+    
+    #[test]
+    fn is_unknown_type() {
+        assert_eq!(
+            Err(VMError::RuntimeError("unknown type 'integer'".to_string())),
+            VM::new(is_chunk("integer")).interpret_verified()
+        );
+    }
+    
+    #[test]
+    fn dup_n() {
+        let chunk = Chunk::new()
+            .write(32, 1)
+            .write(1, 1)
+            .write(32, 1)
+            .write(2, 1)
+            .write(35, 1)
+            .write(2, 1)
+            .write(0, 1);
+        let mut vm = VM::new(chunk);
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(vec![Value::Int(1), Value::Int(2), Value::Int(1), Value::Int(2)], vm.stack);
+    }
+    
+    #[test]
+    fn dup_n_past_bottom_of_stack() {
+        let chunk = Chunk::new().write(32, 1).write(1, 1).write(35, 1).write(2, 1).write(0, 1);
+        
+        assert_eq!(
+            Err(VMError::RuntimeError("stack underflow".to_string())),
+            VM::new(chunk).run()
+        );
+    }
+    
+    /// A chunk for `left and missing`, where
+    /// `missing` is an undefined global, using the
+    /// short-circuit jump pattern
+    fn and_chunk(left: bool) -> Chunk {
+        Chunk::new()
+            .add_constant(Value::Bool(left))
+            .add_constant(Value::from("missing"))
+            .write(1, 1)
+            .write(0, 1)
+            .write(17, 1)
+            .write(0, 1)
+            .write(3, 1)
+            .write(18, 1)
+            .write(12, 1)
+            .write(1, 1)
+            .write(0, 1)
+    }
+    
+    #[test]
+    fn and_short_circuits() {
+        assert_eq!(Ok(Value::Bool(false)), VM::new(and_chunk(false)).interpret_verified());
+        assert_eq!(
+            Err(VMError::RuntimeError("undefined global 'missing'".to_string())),
+            VM::new(and_chunk(true)).interpret_verified()
+        );
+    }
+    
+    #[test]
+    fn run_in_slices() {
+        let mut vm = VM::new(Chunk::loop_countdown(1000));
+        let mut calls = 0;
+        loop {
+            calls += 1;
+            match vm.run_for(100) {
+                RunState::Yielded => {},
+                RunState::Halted => break,
+                RunState::Errored(error) => panic!("unexpected error: {}", error)
+            }
+        }
+        
+        assert!(calls > 1);
+        assert_eq!(
+            Some(&Value::Int(0)),
+            vm.global_slots.get("n").map(|&slot| &vm.globals[slot])
+        );
+    }
+    
+    #[test]
+    fn run_for_error() {
+        let chunk = Chunk::new().write(18, 1).write(0, 1);
+        
+        assert_eq!(
+            RunState::Errored(VMError::RuntimeError("stack underflow".to_string())),
+            VM::new(chunk).run_for(10)
+        );
+    }
+    
+    #[test]
+    fn multiply_and_divide() {
+        assert_eq!(Ok(Value::Int(-21)), VM::new(binary_chunk(7, 36, -3)).interpret_verified());
+        assert_eq!(Ok(Value::Int(3)), VM::new(binary_chunk(7, 37, 2)).interpret_verified());
+        assert_eq!(
+            Err(VMError::RuntimeError("division by zero".to_string())),
+            VM::new(binary_chunk(7, 37, 0)).interpret_verified()
+        );
+    }
+    
+    #[test]
+    fn add_int_and_float() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Int(1))
+            .add_constant(Value::Float(0.5))
+            .write(1, 1)
+            .write(0, 1)
+            .write(1, 1)
+            .write(1, 1)
+            .write(9, 1)
+            .write(0, 1);
+        
+        assert_eq!(Ok(Value::Float(1.5)), VM::new(chunk).interpret_verified());
+    }
+    
+    /// A chunk for `1 + 1.0`, converting the `1` with
+    /// `OP_TO_FLOAT` if `convert` is set
+    fn mixed_add_chunk(convert: bool) -> Chunk {
+        let chunk = Chunk::new()
+            .add_constant(Value::Float(1.0))
+            .write(32, 1)
+            .write(1, 1);
+        let chunk = if convert { chunk.write(38, 1) } else { chunk };
+        chunk.write(1, 1).write(0, 1).write(9, 1).write(0, 1)
+    }
+    
+    #[test]
+    fn mixing_without_strict_mode() {
+        let mut mixed = VM::new(mixed_add_chunk(false));
+        let mut converted = VM::new(mixed_add_chunk(true));
+        
+        assert_eq!(Ok(Value::Float(2.0)), mixed.interpret_verified());
+        assert_eq!(Ok(Value::Float(2.0)), converted.interpret_verified());
+    }
+    
+    #[test]
+    fn strict_mode_rejects_mixing() {
+        let mut mixed = VM::new(mixed_add_chunk(false));
+        mixed.set_strict(true);
+        let mut converted = VM::new(mixed_add_chunk(true));
+        converted.set_strict(true);
+        
+        assert_eq!(
+            Err(VMError::RuntimeError("cannot mix int and float in strict mode".to_string())),
+            mixed.interpret_verified()
+        );
+        assert_eq!(Ok(Value::Float(2.0)), converted.interpret_verified());
+    }
+    
+    #[test]
+    fn share_frozen_chunk() {
+        let chunk = Chunk::arith_add(40, 2).freeze();
+        let threads: Vec<_> = (0..2)
+            .map(|_| {
+                let chunk = chunk.clone();
+                thread::spawn(move || VM::new(chunk).interpret_verified())
+            })
+            .collect();
+        
+        for thread in threads {
+            assert_eq!(Ok(Value::Int(42)), thread.join().unwrap());
+        }
+    }
+    
+    #[test]
+    fn make_array() {
+        let chunk = Chunk::new()
+            .add_constant(Value::from("a"))
+            .write(32, 1)
+            .write(1, 1)
+            .write(1, 1)
+            .write(0, 1)
+            .write(39, 1)
+            .write(2, 1)
+            .write(35, 2)
+            .write(1, 2)
+            .write(30, 2)
+            .write(35, 3)
+            .write(1, 3)
+            .write(7, 3)
+            .write(11, 3)
+            .write(32, 4)
+            .write(1, 4)
+            .write(8, 4)
+            .write(0, 4);
+        let output = SharedOutput::default();
+        let mut vm = VM::new(chunk);
+        vm.set_output(Box::new(output.clone()));
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!("[1, \"a\"]\n", output.contents());
+        assert_eq!(vec![Value::Int(2), Value::from("a")], vm.stack);
+        assert_eq!(1, vm.heap().len());
+    }
+    
+    #[test]
+    fn concatenate_arrays() {
+        let chunk = Chunk::new()
+            .write(32, 1)
+            .write(1, 1)
+            .write(39, 1)
+            .write(1, 1)
+            .write(32, 1)
+            .write(2, 1)
+            .write(39, 1)
+            .write(1, 1)
+            .write(16, 1)
+            .write(35, 1)
+            .write(1, 1)
+            .write(22, 1)
+            .write(0, 1);
+        let mut vm = VM::new(chunk);
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(Value::from("array"), vm.stack[1]);
+        let joined = match vm.stack[0] {
+            Value::Obj(handle) => vm.heap().get(handle),
+            _ => None
+        };
+        assert_eq!(Some(&Obj::Array(vec![Value::Int(1), Value::Int(2)])), joined);
+        assert_eq!(3, vm.heap().len());
+    }
+    
+    #[test]
+    fn collect_garbage_past_threshold() {
+        let chunk = Chunk::new()
+            .add_constant(Value::from("x"))
+            .write(32, 1)
+            .write(2, 1)
+            .write(39, 1)
+            .write(1, 1)
+            .write(19, 1)
+            .write(0, 1)
+            .write(32, 2)
+            .write(1, 2)
+            .write(39, 2)
+            .write(1, 2)
+            .write(39, 3)
+            .write(0, 3)
+            .write(18, 3)
+            .write(39, 4)
+            .write(0, 4)
+            .write(18, 4)
+            .write(39, 5)
+            .write(0, 5)
+            .write(18, 5)
+            .write(0, 5);
+        let popped = Rc::new(RefCell::new(vec![]));
+        let recorded = Rc::clone(&popped);
+        let mut vm = VM::new(chunk);
+        vm.set_gc_threshold(4);
+        vm.set_trace_hook(Box::new(move |event| {
+            if event.opcode == OpCode::Pop {
+                recorded.borrow_mut().extend(event.stack.last().cloned());
+            }
+        }));
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!(3, vm.heap().len());
+        let collected = match popped.borrow()[0] {
+            Value::Obj(handle) => handle,
+            ref other => panic!("expected an object, got {:?}", other)
+        };
+        assert_eq!(None, vm.heap().get(collected));
+        let rooted = match vm.stack[0] {
+            Value::Obj(handle) => vm.heap().get(handle),
+            _ => None
+        };
+        assert_eq!(Some(&Obj::Array(vec![Value::Int(1)])), rooted);
+        let global = &vm.globals[vm.global_slots["x"]];
+        assert_eq!("[2]", vm.heap().show(global).to_string());
+    }
+    
+    #[test]
+    fn sort_array() {
+        let chunk = Chunk::new()
+            .write(32, 1)
+            .write(3, 1)
+            .write(32, 1)
+            .write(1, 1)
+            .write(32, 1)
+            .write(2, 1)
+            .write(39, 1)
+            .write(3, 1)
+            .write(40, 1)
+            .write(0, 1);
+        let mut vm = VM::new(chunk);
+        
+        assert_eq!(Ok(()), vm.run());
+        assert_eq!("[1, 2, 3]", vm.heap().show(&vm.stack[0]).to_string());
+    }
+    
+    #[test]
+    fn sort_mixed_array() {
+        let chunk = Chunk::new()
+            .add_constant(Value::from("a"))
+            .write(32, 1)
+            .write(1, 1)
+            .write(1, 1)
+            .write(0, 1)
+            .write(39, 1)
+            .write(2, 1)
+            .write(40, 1)
+            .write(0, 1);
+        
+        assert_eq!(
+            Err(VMError::RuntimeError("cannot compare 1 and a".to_string())),
+            VM::new(chunk).run()
+        );
+    }
 }
\ No newline at end of file
@@ -1,41 +1,842 @@
 use std::fmt;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::sync::Arc;
+use std::iter::FromIterator;
+use crate::vm::VMError;
+use crate::vm::heap::ObjHandle;
+
+/// The number of constants a pool can hold unless
+/// it's given a different limit
+pub const DEFAULT_CONSTANT_LIMIT: usize = 1_000_000;
 
 /// Represents a constant value in a
 /// chunk
 #[derive(PartialEq, Debug, Clone)]
 pub enum Value {
-    Int(u32),
+    Nil,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Char(char),
+    Str(String),
+    Bytes(Arc<[u8]>),
+    Range {
+        start: i64,
+        end: i64,
+        inclusive: bool
+    },
+    Tuple(Vec<Value>),
+    Native(Arc<Native>),
+    
+    /// An object on the VM's heap, which is compared
+    /// by identity
+    Obj(ObjHandle),
     DoesNotExist
 }
 
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(b) => write!(f, "{}", b),
             Value::Int(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{:?}", x),
+            Value::Char(c) => write!(f, "{}", c),
+            Value::Str(string) => write!(f, "{}", string),
+            Value::Bytes(bytes) => {
+                write!(f, "b\"")?;
+                for byte in bytes.iter() {
+                    write!(f, "{}", std::ascii::escape_default(*byte))?;
+                }
+                write!(f, "\"")
+            },
+            Value::Range { start, end, inclusive: false } => write!(f, "{}..{}", start, end),
+            Value::Range { start, end, inclusive: true } => write!(f, "{}..={}", start, end),
+            Value::Tuple(values) => {
+                write!(f, "(")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    value.fmt_nested(f)?;
+                }
+                write!(f, ")")
+            },
+            Value::Native(native) => write!(f, "<native fn {}>", native.name),
+            Value::Obj(handle) => write!(f, "<object #{}>", handle.index()),
             Value::DoesNotExist => write!(f, "Constant does not exist")
         }
     }
 }
 
+/// The names `Heap::type_name` can return for
+/// values a program can hold
+pub const TYPE_NAMES: &[&str] = &[
+    "nil",
+    "bool",
+    "int",
+    "float",
+    "char",
+    "string",
+    "bytes",
+    "range",
+    "tuple",
+    "function",
+    "array"
+];
+
+impl Value {
+    /// Return the name of the value's type, e.g.
+    /// `"int"` or `"string"`
+    /// 
+    /// Every heap object is an `"object"` here, since
+    /// its type is only known to the heap.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::Char(_) => "char",
+            Value::Str(_) => "string",
+            Value::Bytes(_) => "bytes",
+            Value::Range { .. } => "range",
+            Value::Tuple(_) => "tuple",
+            Value::Native(_) => "function",
+            Value::Obj(_) => "object",
+            Value::DoesNotExist => "invalid"
+        }
+    }
+    
+    /// Format a value that is inside a collection
+    /// 
+    /// Strings and characters are quoted and escaped
+    /// so they can be told apart from other elements,
+    /// even though they are shown as-is on their own.
+    pub fn fmt_nested(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Str(string) => write!(f, "\"{}\"", string.escape_debug()),
+            Value::Char(c) => write!(f, "'{}'", c.escape_debug()),
+            other => write!(f, "{}", other)
+        }
+    }
+    
+    /// Apply an arithmetic operator to two numbers
+    /// 
+    /// Two ints give an int, and overflow is an error.
+    /// `Int / Int` is integer division, which truncates
+    /// toward zero and fails on division by zero. If
+    /// either operand is a float, the other is promoted
+    /// to a float and the result is a float, so `7 / 2`
+    /// is `3` but `7 / 2.0` is `3.5`.
+    pub fn arithmetic(self, other: Value, op: ArithmeticOp) -> Result<Value, String> {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => {
+                let result = match op {
+                    ArithmeticOp::Add => a.checked_add(b),
+                    ArithmeticOp::Subtract => a.checked_sub(b),
+                    ArithmeticOp::Multiply => a.checked_mul(b),
+                    ArithmeticOp::Divide if b == 0 => {
+                        return Err("division by zero".to_string());
+                    },
+                    ArithmeticOp::Divide => a.checked_div(b)
+                };
+                result.map(Value::Int).ok_or_else(|| "integer overflow".to_string())
+            },
+            (Value::Int(a), Value::Float(b)) => Ok(Value::Float(op.apply(a as f64, b))),
+            (Value::Float(a), Value::Int(b)) => Ok(Value::Float(op.apply(a, b as f64))),
+            (Value::Float(a), Value::Float(b)) => Ok(Value::Float(op.apply(a, b))),
+            (a, b) => Err(format!("cannot {} {} and {}", op.verb(), a, b))
+        }
+    }
+    
+    /// Write the value as Crayne source
+    /// 
+    /// Unlike `Display`, strings are quoted and
+    /// escaped, so that parsing the source of a
+    /// non-negative int, a finite float, a bool, or a
+    /// string gives back an equal value. (A negative
+    /// number parses as the negation of a literal.)
+    /// Other values are written the way they're
+    /// displayed.
+    pub fn to_source(&self) -> String {
+        match self {
+            Value::Str(string) => {
+                let mut source = String::from("\"");
+                for c in string.chars() {
+                    match c {
+                        '"' => source.push_str("\\\""),
+                        '\\' => source.push_str("\\\\"),
+                        '\n' => source.push_str("\\n"),
+                        '\r' => source.push_str("\\r"),
+                        '\t' => source.push_str("\\t"),
+                        '\0' => source.push_str("\\0"),
+                        c if c.is_control() => source.push_str(&format!("\\u{{{:x}}}", c as u32)),
+                        c => source.push(c)
+                    }
+                }
+                source.push('"');
+                source
+            },
+            Value::Tuple(values) => {
+                let values: Vec<String> = values.iter().map(Value::to_source).collect();
+                format!("({})", values.join(", "))
+            },
+            other => other.to_string()
+        }
+    }
+    
+    /// Show an integer in the given radix with the
+    /// prefix of the matching literal, e.g. `0xff`,
+    /// or return `None` for any other value
+    /// 
+    /// Negative integers keep their sign in front
+    /// of the prefix, e.g. `-0b101`.
+    pub fn to_radix_string(&self, radix: Radix) -> Option<String> {
+        let i = match self {
+            Value::Int(i) => *i,
+            _ => return None
+        };
+        let sign = if i < 0 { "-" } else { "" };
+        let magnitude = i.unsigned_abs();
+        let digits = match radix {
+            Radix::Binary => format!("0b{:b}", magnitude),
+            Radix::Octal => format!("0o{:o}", magnitude),
+            Radix::Decimal => magnitude.to_string(),
+            Radix::Hex => format!("0x{:x}", magnitude)
+        };
+        Some(format!("{}{}", sign, digits))
+    }
+    
+    /// Order two values of the same type
+    /// 
+    /// Numbers, characters, strings, and bytes use
+    /// their natural order, `false` comes before
+    /// `true`, and tuples are compared element by
+    /// element. Values of different types and NaN
+    /// floats have no order, so they are an error.
+    pub fn total_cmp(&self, other: &Value) -> Result<Ordering, String> {
+        match (self, other) {
+            (Value::Bool(a), Value::Bool(b)) => Ok(a.cmp(b)),
+            (Value::Int(a), Value::Int(b)) => Ok(a.cmp(b)),
+            (Value::Float(a), Value::Float(b)) => a.partial_cmp(b)
+                .ok_or_else(|| "cannot order NaN".to_string()),
+            (Value::Char(a), Value::Char(b)) => Ok(a.cmp(b)),
+            (Value::Str(a), Value::Str(b)) => Ok(a.cmp(b)),
+            (Value::Bytes(a), Value::Bytes(b)) => Ok(a.cmp(b)),
+            (Value::Tuple(a), Value::Tuple(b)) => {
+                for (a, b) in a.iter().zip(b) {
+                    match a.total_cmp(b)? {
+                        Ordering::Equal => {},
+                        ordering => return Ok(ordering)
+                    }
+                }
+                Ok(a.len().cmp(&b.len()))
+            },
+            (a, b) => Err(format!("cannot compare {} and {}", a, b))
+        }
+    }
+}
+
+/// The operators `Value::arithmetic` applies
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum ArithmeticOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide
+}
+
+impl ArithmeticOp {
+    /// Apply the operator to two floats
+    fn apply(self, a: f64, b: f64) -> f64 {
+        match self {
+            ArithmeticOp::Add => a + b,
+            ArithmeticOp::Subtract => a - b,
+            ArithmeticOp::Multiply => a * b,
+            ArithmeticOp::Divide => a / b
+        }
+    }
+    
+    /// The verb for the operator in error messages,
+    /// e.g. "cannot add a and b"
+    fn verb(self) -> &'static str {
+        match self {
+            ArithmeticOp::Add => "add",
+            ArithmeticOp::Subtract => "subtract",
+            ArithmeticOp::Multiply => "multiply",
+            ArithmeticOp::Divide => "divide"
+        }
+    }
+}
+
+/// The bases integers can be shown in
+#[derive(PartialEq, Eq, Debug, Default, Clone, Copy)]
+pub enum Radix {
+    Binary,
+    Octal,
+    #[default]
+    Decimal,
+    Hex
+}
+
+/// Sort values in place in the order of
+/// `Value::total_cmp`, or return an error if any of
+/// them can't be ordered against each other
+/// 
+/// Every value is checked before sorting, so the
+/// sort always sees a total order.
+pub fn sort_values(values: &mut [Value]) -> Result<(), String> {
+    if let Some(first) = values.first() {
+        for value in values.iter() {
+            check_orderable(first, value)?;
+        }
+    }
+    values.sort_by(sort_order);
+    Ok(())
+}
+
+/// Return an error if `value` can't be ordered
+/// against `first`
+/// 
+/// Tuples must be the same length, so that if every
+/// value can be ordered against the first one, every
+/// pair of values can be ordered.
+fn check_orderable(first: &Value, value: &Value) -> Result<(), String> {
+    match (first, value) {
+        (_, Value::Float(f)) if f.is_nan() => Err("cannot order NaN".to_string()),
+        (Value::Bool(_), Value::Bool(_))
+        | (Value::Int(_), Value::Int(_))
+        | (Value::Float(_), Value::Float(_))
+        | (Value::Char(_), Value::Char(_))
+        | (Value::Str(_), Value::Str(_))
+        | (Value::Bytes(_), Value::Bytes(_)) => Ok(()),
+        (Value::Tuple(a), Value::Tuple(b)) if a.len() == b.len() => {
+            a.iter().zip(b).try_for_each(|(a, b)| check_orderable(a, b))
+        },
+        (a, b) => Err(format!("cannot compare {} and {}", a, b))
+    }
+}
+
+/// Order two values that `check_orderable` accepts,
+/// using `f64::total_cmp` for floats
+fn sort_order(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Float(a), Value::Float(b)) => a.total_cmp(b),
+        (Value::Tuple(a), Value::Tuple(b)) => a.iter()
+            .zip(b)
+            .map(|(a, b)| sort_order(a, b))
+            .find(|&ordering| ordering != Ordering::Equal)
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        (a, b) => a.total_cmp(b).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// The signature of a function implemented in Rust
+/// that can be called from Crayne
+pub type NativeFn = dyn Fn(&[Value]) -> Result<Value, String> + Send + Sync;
+
+/// A function implemented in Rust, along with
+/// the number of arguments it takes
+pub struct Native {
+    /// The name the function is defined under
+    pub name: String,
+    
+    /// The number of arguments the function takes
+    pub arity: u8,
+    
+    /// The implementation of the function
+    pub function: Box<NativeFn>
+}
+
+impl fmt::Debug for Native {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Native({}/{})", self.name, self.arity)
+    }
+}
+
+/// Natives are only equal to themselves, since
+/// functions can't be compared
+impl PartialEq for Native {
+    fn eq(&self, other: &Native) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Value::Int(i)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(x: f64) -> Self {
+        Value::Float(x)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(string: &str) -> Self {
+        Value::Str(string.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(string: String) -> Self {
+        Value::Str(string)
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = String;
+    
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Bool(b) => Ok(b),
+            other => Err(format!("expected a bool, found {}", other))
+        }
+    }
+}
+
+impl TryFrom<Value> for i64 {
+    type Error = String;
+    
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Int(i) => Ok(i),
+            other => Err(format!("expected an integer, found {}", other))
+        }
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = String;
+    
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Float(x) => Ok(x),
+            other => Err(format!("expected a float, found {}", other))
+        }
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = String;
+    
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Str(string) => Ok(string),
+            other => Err(format!("expected a string, found {}", other))
+        }
+    }
+}
+
 /// A vector that contains the constants
 /// for a specific chunk
-#[derive(PartialEq, Debug, Default)]
-pub struct ConstantPool(Vec<Value>);
+/// 
+/// The pool has a limit on how many constants
+/// the pool will hold, as a guard against
+/// programs that would use up memory with a huge
+/// pool. It is separate from how many constants
+/// an instruction's operand can refer to.
+#[derive(Debug, Clone)]
+pub struct ConstantPool {
+    constants: Vec<Value>,
+    limit: usize
+}
 
 impl ConstantPool {
     /// Create a new constant pool
     pub fn new() -> Self {
-        ConstantPool(vec![])
+        Self::with_capacity(0)
+    }
+    
+    /// Create a new constant pool with room for
+    /// `capacity` constants before reallocating
+    pub fn with_capacity(capacity: usize) -> Self {
+        ConstantPool {
+            constants: Vec::with_capacity(capacity),
+            limit: DEFAULT_CONSTANT_LIMIT
+        }
+    }
+    
+    /// Set the most constants that the pool will
+    /// hold
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+    }
+    
+    /// Return the most constants that the pool will
+    /// hold
+    pub fn limit(&self) -> usize {
+        self.limit
     }
     
     /// Add a constant to the pool
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if the pool is already at its limit.
     pub fn write(mut self, value: Value) -> Self {
-        self.0.push(value);
+        self.push(value);
         self
     }
     
+    /// Add a constant to the pool in place and
+    /// return its index
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if the pool is already at its limit.
+    pub fn push(&mut self, value: Value) -> usize {
+        self.try_push(value).unwrap_or_else(|error| panic!("{}", error))
+    }
+    
+    /// Add a constant to the pool in place and
+    /// return its index, or a compile error if the
+    /// pool is already at its limit
+    pub fn try_push(&mut self, value: Value) -> Result<usize, VMError> {
+        if self.constants.len() >= self.limit {
+            return Err(VMError::CompileError(format!(
+                "too many constants (the limit is {})",
+                self.limit
+            )));
+        }
+        self.constants.push(value);
+        Ok(self.constants.len() - 1)
+    }
+    
+    /// Add a constant to the pool unless an equal
+    /// constant is already in it
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if the constant is new and the pool is
+    /// already at its limit.
+    pub fn write_unique(mut self, value: Value) -> Self {
+        self.push_unique(value);
+        self
+    }
+    
+    /// Add a constant to the pool in place unless an
+    /// equal constant is already in it, and return the
+    /// index of the constant
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if the constant is new and the pool is
+    /// already at its limit.
+    pub fn push_unique(&mut self, value: Value) -> usize {
+        match self.constants.iter().position(|constant| *constant == value) {
+            Some(index) => index,
+            None => self.push(value)
+        }
+    }
+    
     /// Get a constant from the pool by index
     pub fn get_const(&self, index: usize) -> Value {
-        self.0.get(index).cloned().unwrap_or(Value::DoesNotExist)
+        self.constants.get(index).cloned().unwrap_or(Value::DoesNotExist)
+    }
+    
+    /// Return the number of constants in the pool
+    pub fn len(&self) -> usize {
+        self.constants.len()
+    }
+    
+    /// Return whether the pool has no constants
+    pub fn is_empty(&self) -> bool {
+        self.constants.is_empty()
+    }
+}
+
+/// Pools are equal if they hold the same constants,
+/// whatever their limits
+impl PartialEq for ConstantPool {
+    fn eq(&self, other: &Self) -> bool {
+        self.constants == other.constants
+    }
+}
+
+impl Default for ConstantPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FromIterator<Value> for ConstantPool {
+    fn from_iter<I: IntoIterator<Item = Value>>(iter: I) -> Self {
+        let mut pool = Self::new();
+        pool.extend(iter);
+        pool
+    }
+}
+
+/// # Panics
+/// 
+/// Panics if the pool reaches its limit before all
+/// of the constants are added.
+impl Extend<Value> for ConstantPool {
+    fn extend<I: IntoIterator<Item = Value>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn display_bytes() {
+        let bytes = Value::Bytes(Arc::from(&b"hi\n\x00\xff"[..]));
+        
+        assert_eq!("b\"hi\\n\\x00\\xff\"", bytes.to_string());
+    }
+    
+    #[test]
+    fn bytes_compare_contents() {
+        let a = Value::Bytes(Arc::from(&b"abc"[..]));
+        let b = Value::Bytes(Arc::from(vec![b'a', b'b', b'c']));
+        
+        assert_eq!(a, b);
+    }
+    
+    #[test]
+    fn bool_round_trip() {
+        assert_eq!(Value::Bool(true), Value::from(true));
+        assert_eq!(Ok(true), bool::try_from(Value::from(true)));
+    }
+    
+    #[test]
+    fn int_round_trip() {
+        assert_eq!(Value::Int(-7), Value::from(-7));
+        assert_eq!(Ok(-7), i64::try_from(Value::from(-7)));
+    }
+    
+    #[test]
+    fn float_round_trip() {
+        assert_eq!(Value::Float(0.5), Value::from(0.5));
+        assert_eq!(Ok(0.5), f64::try_from(Value::from(0.5)));
+    }
+    
+    #[test]
+    fn string_round_trip() {
+        assert_eq!(Value::Str("hi".to_string()), Value::from("hi"));
+        assert_eq!(Value::Str("hi".to_string()), Value::from("hi".to_string()));
+        assert_eq!(Ok("hi".to_string()), String::try_from(Value::from("hi")));
+    }
+    
+    #[test]
+    fn convert_wrong_type() {
+        assert_eq!(
+            Err("expected an integer, found hi".to_string()),
+            i64::try_from(Value::from("hi"))
+        );
+        assert!(bool::try_from(Value::Nil).is_err());
+        assert!(f64::try_from(Value::Int(1)).is_err());
+        assert!(String::try_from(Value::Char('a')).is_err());
+    }
+    
+    #[test]
+    fn collect_constant_pool() {
+        let pool: ConstantPool = vec![Value::Int(1), Value::Int(2)].into_iter().collect();
+        
+        assert_eq!(2, pool.len());
+        assert_eq!(Value::Int(1), pool.get_const(0));
+        assert_eq!(Value::Int(2), pool.get_const(1));
+    }
+    
+    #[test]
+    fn reuse_equal_constants() {
+        let mut pool = ConstantPool::new();
+        
+        assert_eq!(0, pool.push_unique(Value::Int(1)));
+        assert_eq!(1, pool.push_unique(Value::Int(2)));
+        assert_eq!(0, pool.push_unique(Value::Int(1)));
+        assert_eq!(2, pool.len());
+    }
+    
+    #[test]
+    fn extend_constant_pool() {
+        let mut pool = ConstantPool::with_capacity(3).write(Value::Int(1));
+        pool.extend(vec![Value::Int(2), Value::Int(3)]);
+        let expected = ConstantPool::new()
+            .write(Value::Int(1))
+            .write(Value::Int(2))
+            .write(Value::Int(3));
+        
+        assert_eq!(expected, pool);
+    }
+    
+    #[test]
+    fn constant_limit() {
+        let mut pool = ConstantPool::new();
+        pool.set_limit(2);
+        
+        assert_eq!(Ok(0), pool.try_push(Value::Int(1)));
+        assert_eq!(Ok(1), pool.try_push(Value::Int(2)));
+        assert_eq!(
+            Err(VMError::CompileError("too many constants (the limit is 2)".to_string())),
+            pool.try_push(Value::Int(3))
+        );
+        assert_eq!(2, pool.len());
+    }
+    
+    #[test]
+    fn constant_limit_applies_to_every_push() {
+        let mut pool = ConstantPool::new();
+        pool.set_limit(1);
+        pool.push(Value::Int(1));
+        
+        assert_eq!(0, pool.push_unique(Value::Int(1)));
+        for add in [
+            |pool: &mut ConstantPool| { pool.push(Value::Int(2)); },
+            |pool: &mut ConstantPool| { pool.push_unique(Value::Int(2)); },
+            |pool: &mut ConstantPool| pool.extend(vec![Value::Int(2)]),
+            |pool: &mut ConstantPool| { let _ = pool.clone().write(Value::Int(2)); }
+        ] {
+            let mut pool = pool.clone();
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| add(&mut pool)));
+            
+            assert!(result.is_err());
+            assert_eq!(1, pool.len());
+        }
+    }
+    
+    #[test]
+    fn pool_equality_ignores_limit() {
+        let mut limited = ConstantPool::new().write(Value::Int(1));
+        limited.set_limit(5);
+        
+        assert_eq!(ConstantPool::new().write(Value::Int(1)), limited);
+        assert_ne!(ConstantPool::new(), limited);
+    }
+    
+    #[test]
+    fn sort_ints() {
+        let mut values = vec![Value::Int(3), Value::Int(1), Value::Int(2)];
+        
+        assert_eq!(Ok(()), sort_values(&mut values));
+        assert_eq!(vec![Value::Int(1), Value::Int(2), Value::Int(3)], values);
+    }
+    
+    #[test]
+    fn sort_mixed_types() {
+        let mut values = vec![Value::Int(3), Value::from("a"), Value::Int(2)];
+        
+        assert!(sort_values(&mut values).is_err());
+    }
+    
+    #[test]
+    fn sort_nan() {
+        let mut values = vec![Value::Float(1.0), Value::Float(f64::NAN)];
+        
+        assert_eq!(Err("cannot order NaN".to_string()), sort_values(&mut values));
+    }
+    
+    #[test]
+    fn sort_many_mixed_types() {
+        let mut values: Vec<Value> = (0..25)
+            .map(|i| if i % 3 == 0 { Value::from("a") } else { Value::Int(25 - i) })
+            .collect();
+        let unsorted = values.clone();
+        
+        assert_eq!(Err("cannot compare a and 24".to_string()), sort_values(&mut values));
+        assert_eq!(unsorted, values);
+        
+        let mut values: Vec<Value> = (0..25).map(|i| Value::Float(i as f64)).collect();
+        values[20] = Value::Float(f64::NAN);
+        
+        assert_eq!(Err("cannot order NaN".to_string()), sort_values(&mut values));
+    }
+    
+    #[test]
+    fn sort_tuples() {
+        let mut values = vec![
+            Value::Tuple(vec![Value::Int(2), Value::Float(-0.5)]),
+            Value::Tuple(vec![Value::Int(1), Value::Float(3.0)]),
+            Value::Tuple(vec![Value::Int(2), Value::Float(-1.0)])
+        ];
+        
+        assert_eq!(Ok(()), sort_values(&mut values));
+        assert_eq!(Value::Tuple(vec![Value::Int(1), Value::Float(3.0)]), values[0]);
+        assert_eq!(Value::Tuple(vec![Value::Int(2), Value::Float(-0.5)]), values[2]);
+        
+        let mut values = vec![
+            Value::Tuple(vec![Value::Int(1)]),
+            Value::Tuple(vec![Value::Int(1), Value::Int(2)])
+        ];
+        
+        assert!(sort_values(&mut values).is_err());
+    }
+    
+    #[test]
+    fn display_top_level_string() {
+        assert_eq!("a \"b\"", Value::from("a \"b\"").to_string());
+    }
+    
+    #[test]
+    fn display_nested_strings() {
+        let tuple = Value::Tuple(vec![
+            Value::from("a"),
+            Value::from("b\n"),
+            Value::Char('c'),
+            Value::Int(1)
+        ]);
+        
+        assert_eq!("(\"a\", \"b\\n\", 'c', 1)", tuple.to_string());
+    }
+    
+    #[test]
+    fn radix_strings() {
+        assert_eq!(Some("0xff".to_string()), Value::Int(255).to_radix_string(Radix::Hex));
+        assert_eq!(Some("0b101".to_string()), Value::Int(5).to_radix_string(Radix::Binary));
+        assert_eq!(Some("-0o17".to_string()), Value::Int(-15).to_radix_string(Radix::Octal));
+        assert_eq!(None, Value::Float(1.0).to_radix_string(Radix::Hex));
+    }
+    
+    #[test]
+    fn int_arithmetic_stays_int() {
+        assert_eq!(Ok(Value::Int(5)), Value::Int(2).arithmetic(Value::Int(3), ArithmeticOp::Add));
+        assert_eq!(Ok(Value::Int(-1)), Value::Int(2).arithmetic(Value::Int(3), ArithmeticOp::Subtract));
+        assert_eq!(Ok(Value::Int(6)), Value::Int(2).arithmetic(Value::Int(3), ArithmeticOp::Multiply));
+        assert_eq!(
+            Err("integer overflow".to_string()),
+            Value::Int(i64::MAX).arithmetic(Value::Int(2), ArithmeticOp::Multiply)
+        );
+    }
+    
+    #[test]
+    fn float_promotion() {
+        assert_eq!(Ok(Value::Float(3.5)), Value::Int(3).arithmetic(Value::Float(0.5), ArithmeticOp::Add));
+        assert_eq!(Ok(Value::Float(4.0)), Value::Float(3.0).arithmetic(Value::Int(-1), ArithmeticOp::Subtract));
+        assert_eq!(Ok(Value::Float(1.0)), Value::Float(0.5).arithmetic(Value::Float(2.0), ArithmeticOp::Multiply));
+        assert_eq!(
+            Err("cannot multiply 2 and true".to_string()),
+            Value::Int(2).arithmetic(Value::Bool(true), ArithmeticOp::Multiply)
+        );
+    }
+    
+    #[test]
+    fn int_and_float_division() {
+        assert_eq!(Ok(Value::Int(3)), Value::Int(7).arithmetic(Value::Int(2), ArithmeticOp::Divide));
+        assert_eq!(Ok(Value::Int(-3)), Value::Int(-7).arithmetic(Value::Int(2), ArithmeticOp::Divide));
+        assert_eq!(Ok(Value::Float(3.5)), Value::Int(7).arithmetic(Value::Float(2.0), ArithmeticOp::Divide));
+        assert_eq!(
+            Err("division by zero".to_string()),
+            Value::Int(7).arithmetic(Value::Int(0), ArithmeticOp::Divide)
+        );
+        assert_eq!(
+            Ok(Value::Float(f64::INFINITY)),
+            Value::Float(7.0).arithmetic(Value::Int(0), ArithmeticOp::Divide)
+        );
     }
 }
\ No newline at end of file
@@ -0,0 +1,146 @@
+//! Check that a chunk's bytecode is well-formed
+//! before it is run
+
+use std::collections::HashSet;
+use crate::vm::chunk::{Chunk, OpCode};
+use crate::vm::VMError;
+
+/// Check that a chunk can be run safely
+/// 
+/// The chunk is rejected if it has an invalid or
+/// debug-only opcode, an instruction whose operands
+/// run past the end of the code, a constant index
+/// outside the constant pool, or a jump (forward or
+/// backward) that doesn't land on the start of an
+/// instruction.
+pub fn verify(chunk: &Chunk) -> Result<(), VMError> {
+    let mut starts = HashSet::new();
+    let mut jumps = vec![];
+    let mut offset = 0;
+    
+    while offset < chunk.size() {
+        starts.insert(offset);
+        let opcode = OpCode::from(chunk.byte_at(offset));
+        let next = offset + 1 + opcode.operand_len();
+        
+        if let OpCode::Invalid(byte) = opcode {
+            return Err(error(format!("invalid opcode {:#04X}", byte), chunk, offset));
+        }
+        if opcode.is_debug_only() {
+            return Err(error(format!("debug-only opcode {:?}", opcode), chunk, offset));
+        }
+        if next > chunk.size() {
+            return Err(error(format!("truncated operand for {:?}", opcode), chunk, offset));
+        }
+        
+        match opcode {
+            OpCode::Constant |
+            OpCode::GetGlobal |
+            OpCode::DefineGlobal |
+            OpCode::SetGlobal => {
+                let index = chunk.byte_at(offset + 1) as usize;
+                if index >= chunk.constant_count() {
+                    return Err(error(
+                        format!("constant index {} out of range", index),
+                        chunk,
+                        offset
+                    ));
+                }
+            },
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop => {
+                jumps.push((offset, chunk.jump_target(offset).unwrap()));
+            },
+            _ => {}
+        }
+        
+        offset = next;
+    }
+    
+    for (offset, target) in jumps {
+        let lands = target >= 0 &&
+            (target as usize == chunk.size() || starts.contains(&(target as usize)));
+        if !lands {
+            return Err(error(
+                format!("jump to {} is not the start of an instruction", target),
+                chunk,
+                offset
+            ));
+        }
+    }
+    
+    Ok(())
+}
+
+/// Build a compile error naming the offset and line
+/// of the instruction that failed verification
+fn error(message: String, chunk: &Chunk, offset: usize) -> VMError {
+    VMError::CompileError(format!(
+        "{} at offset {} (line {})",
+        message,
+        offset,
+        chunk.get_line(offset)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::value::Value;
+    
+    #[test]
+    fn valid_chunk() {
+        assert_eq!(Ok(()), verify(&Chunk::test()));
+    }
+    
+    #[test]
+    fn constant_out_of_range() {
+        let chunk = Chunk::new()
+            .write(1, 1)
+            .write(0, 1)
+            .write(0, 1);
+        
+        assert_eq!(
+            Err(VMError::CompileError(
+                "constant index 0 out of range at offset 0 (line 1)".to_string()
+            )),
+            verify(&chunk)
+        );
+    }
+    
+    #[test]
+    fn truncated_operand() {
+        let chunk = Chunk::new()
+            .write(14, 1)
+            .write(0, 1);
+        
+        assert!(verify(&chunk).is_err());
+    }
+    
+    #[test]
+    fn jump_into_operand() {
+        let chunk = Chunk::new()
+            .add_constant(Value::Int(1))
+            .write(14, 1)
+            .write(0, 1)
+            .write(1, 1)
+            .write(1, 2)
+            .write(0, 2)
+            .write(0, 2);
+        
+        assert_eq!(
+            Err(VMError::CompileError(
+                "jump to 4 is not the start of an instruction at offset 0 (line 1)".to_string()
+            )),
+            verify(&chunk)
+        );
+    }
+    
+    #[test]
+    fn debug_only_opcode() {
+        let chunk = Chunk::new()
+            .write(15, 1)
+            .write(0, 1);
+        
+        assert!(verify(&chunk).is_err());
+    }
+}
\ No newline at end of file